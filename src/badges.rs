@@ -0,0 +1,221 @@
+use crate::theme::{ColorSupport, Style};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use twitch_irc::message::Badge;
+
+/// How a badge looks when the `[badges.glyphs]` table doesn't say.
+struct DefaultGlyph {
+    name: &'static str,
+    glyph: &'static str,
+    style: &'static str,
+}
+
+/// The built-in badge glyphs, badges not here or in the config aren't shown.
+const DEFAULT_GLYPHS: [DefaultGlyph; 5] = [
+    DefaultGlyph {
+        name: "broadcaster",
+        glyph: "B",
+        style: "bold white on red",
+    },
+    DefaultGlyph {
+        name: "moderator",
+        glyph: "M",
+        style: "bold white on green",
+    },
+    DefaultGlyph {
+        name: "vip",
+        glyph: "V",
+        style: "bold white on magenta",
+    },
+    DefaultGlyph {
+        name: "founder",
+        glyph: "F",
+        style: "bold black on yellow",
+    },
+    DefaultGlyph {
+        name: "subscriber",
+        glyph: "S",
+        style: "bold white on blue",
+    },
+];
+
+/// A badge as it's shown in front of a name: a glyph or short tag, in a
+/// style of its own when it's written as `{ glyph = "M", style = "green" }`.
+/// An empty glyph hides the badge.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum BadgeGlyph {
+    Plain(String),
+    Styled {
+        glyph: String,
+        #[serde(default)]
+        style: Style,
+    },
+}
+
+impl BadgeGlyph {
+    fn is_hidden(&self) -> bool {
+        match self {
+            BadgeGlyph::Plain(glyph) | BadgeGlyph::Styled { glyph, .. } => glyph.is_empty(),
+        }
+    }
+
+    fn paint(&self, support: ColorSupport) -> String {
+        match self {
+            BadgeGlyph::Plain(glyph) => glyph.clone(),
+            BadgeGlyph::Styled { glyph, style } => style.downgrade(support).paint(glyph),
+        }
+    }
+}
+
+/// The glyph for `badge` from the config's `glyphs`, by `name/version` and
+/// then by name, or else the built-in one.
+fn glyph(glyphs: &BTreeMap<String, BadgeGlyph>, badge: &Badge) -> Option<BadgeGlyph> {
+    let versioned = format!("{}/{}", badge.name, badge.version);
+    if let Some(glyph) = glyphs.get(&versioned).or_else(|| glyphs.get(&badge.name)) {
+        return Some(glyph.clone());
+    }
+    DEFAULT_GLYPHS
+        .iter()
+        .find(|default| default.name == badge.name)
+        .map(|default| BadgeGlyph::Styled {
+            glyph: default.glyph.to_string(),
+            style: Style::parse(default.style).unwrap(),
+        })
+}
+
+/// The glyphs of `badges` in Twitch's order, followed by a space when
+/// there are any.
+pub fn render_badges(
+    badges: &[Badge],
+    glyphs: &BTreeMap<String, BadgeGlyph>,
+    support: ColorSupport,
+) -> String {
+    let mut rendered = String::new();
+    for glyph in badges.iter().filter_map(|badge| glyph(glyphs, badge)) {
+        if !glyph.is_hidden() {
+            rendered.push_str(&glyph.paint(support));
+        }
+    }
+    if !rendered.is_empty() {
+        rendered.push(' ');
+    }
+    rendered
+}
+
+/// `badges` spelled out, with how long the chatter has been subscribed
+/// from `badge_info`, which counts every month where the badge version
+/// only goes up in steps.
+pub fn describe_badges(badges: &[Badge], badge_info: &[Badge]) -> String {
+    let descriptions: Vec<String> = badges
+        .iter()
+        .map(|badge| {
+            let name = title(&badge.name);
+            match badge.name.as_str() {
+                "subscriber" | "founder" => {
+                    let months = badge_info
+                        .iter()
+                        .find(|info| info.name == badge.name)
+                        .map(|info| info.version.as_str());
+                    match months {
+                        Some(months) => format!("{} ({} months)", name, months),
+                        None => name,
+                    }
+                }
+                _ if badge.version != "1" && badge.version != "0" => {
+                    format!("{} ({})", name, badge.version)
+                }
+                _ => name,
+            }
+        })
+        .collect();
+    if descriptions.is_empty() {
+        return "no badges".to_string();
+    }
+    descriptions.join(", ")
+}
+
+/// `sub-gift-leader` as `Sub gift leader`.
+fn title(name: &str) -> String {
+    let name = name.replace(['-', '_'], " ");
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => name,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn badge(name: &str, version: &str) -> Badge {
+        Badge {
+            name: name.to_string(),
+            version: version.to_string(),
+        }
+    }
+
+    fn render(badges: &[Badge], glyphs: &[(&str, &str)]) -> String {
+        let glyphs = glyphs
+            .iter()
+            .map(|(name, glyph)| (name.to_string(), BadgeGlyph::Plain(glyph.to_string())))
+            .collect();
+        render_badges(badges, &glyphs, ColorSupport::TrueColor)
+    }
+
+    #[test]
+    fn default_glyph_styles_parse() {
+        for default in &DEFAULT_GLYPHS {
+            assert!(Style::parse(default.style).is_ok(), "{}", default.name);
+        }
+    }
+
+    #[test]
+    fn configured_glyphs_by_version_then_name() {
+        let glyphs = [("subscriber/12", "Y"), ("subscriber", "s")];
+        assert_eq!(render(&[badge("subscriber", "12")], &glyphs), "Y ");
+        assert_eq!(render(&[badge("subscriber", "6")], &glyphs), "s ");
+        assert_eq!(
+            render(&[badge("vip", "1"), badge("subscriber", "6")], &glyphs),
+            format!(
+                "{}s ",
+                Style::parse("bold white on magenta").unwrap().paint("V")
+            )
+        );
+    }
+
+    #[test]
+    fn built_in_glyphs_and_hidden_badges() {
+        assert_eq!(
+            render(&[badge("moderator", "1")], &[]),
+            format!(
+                "{} ",
+                Style::parse("bold white on green").unwrap().paint("M")
+            )
+        );
+        assert_eq!(render(&[badge("moderator", "1")], &[("moderator", "")]), "");
+        assert_eq!(render(&[badge("glhf-pledge", "1")], &[]), "");
+        assert_eq!(render(&[], &[]), "");
+    }
+
+    #[test]
+    fn describes_badges() {
+        assert_eq!(describe_badges(&[], &[]), "no badges");
+        assert_eq!(
+            describe_badges(
+                &[
+                    badge("moderator", "1"),
+                    badge("subscriber", "12"),
+                    badge("bits", "100")
+                ],
+                &[badge("subscriber", "14")]
+            ),
+            "Moderator, Subscriber (14 months), Bits (100)"
+        );
+        assert_eq!(
+            describe_badges(&[badge("founder", "0"), badge("sub-gift-leader", "1")], &[]),
+            "Founder, Sub gift leader"
+        );
+    }
+}
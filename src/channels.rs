@@ -0,0 +1,618 @@
+use crate::moderation::format_duration;
+use crate::scrollback::{Line, Scrollback};
+use std::{
+    collections::{BTreeSet, HashMap, HashSet, VecDeque},
+    time::{Duration, Instant},
+};
+use twitch_irc::message::{Badge, FollowersOnlyMode, RoomStateMessage};
+
+/// Most chatters and recently joined channels kept for Tab completion.
+const RECENT_SIZE: usize = 500;
+
+/// What a chatter's last message in a channel said about them.
+pub struct ChatterInfo {
+    pub name: String,
+    pub badges: Vec<Badge>,
+    pub badge_info: Vec<Badge>,
+}
+
+/// What a chatter is in a channel, going by their badges. The viewer list
+/// is grouped by it, in this order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    Broadcaster,
+    Moderator,
+    Vip,
+    Viewer,
+}
+
+impl Role {
+    pub fn title(&self) -> &'static str {
+        match self {
+            Role::Broadcaster => "Broadcaster",
+            Role::Moderator => "Moderators",
+            Role::Vip => "VIPs",
+            Role::Viewer => "Viewers",
+        }
+    }
+}
+
+/// The chat modes a channel is in, as the last `ROOMSTATE`s said.
+#[derive(Default)]
+pub struct RoomState {
+    pub emote_only: bool,
+    /// How long chatters must have followed for, when only followers can chat.
+    pub followers_only: Option<Duration>,
+    pub r9k: bool,
+    /// How long chatters wait between messages, zero when slow mode is off.
+    pub slow: Duration,
+    pub subs_only: bool,
+}
+
+impl RoomState {
+    /// Take in the settings `roomstate` has, after joining that's all of
+    /// them and later only the ones that changed.
+    pub fn update(&mut self, roomstate: &RoomStateMessage) {
+        if let Some(emote_only) = roomstate.emote_only {
+            self.emote_only = emote_only;
+        }
+        if let Some(followers_only) = &roomstate.follwers_only {
+            self.followers_only = match followers_only {
+                FollowersOnlyMode::Enabled(duration) => Some(*duration),
+                FollowersOnlyMode::Disabled => None,
+            };
+        }
+        if let Some(r9k) = roomstate.r9k {
+            self.r9k = r9k;
+        }
+        if let Some(slow) = roomstate.slow_mode {
+            self.slow = slow;
+        }
+        if let Some(subs_only) = roomstate.subscribers_only {
+            self.subs_only = subs_only;
+        }
+    }
+
+    /// The modes that are on, e.g. `slow 30s, subs-only`.
+    pub fn describe(&self) -> String {
+        let mut modes = Vec::new();
+        if self.emote_only {
+            modes.push("emote-only".to_string());
+        }
+        match self.followers_only {
+            Some(duration) if duration.is_zero() => modes.push("followers-only".to_string()),
+            Some(duration) => modes.push(format!("followers-only {}", format_duration(duration))),
+            None => {}
+        }
+        if self.r9k {
+            modes.push("r9k".to_string());
+        }
+        if !self.slow.is_zero() {
+            modes.push(format!("slow {}", format_duration(self.slow)));
+        }
+        if self.subs_only {
+            modes.push("subs-only".to_string());
+        }
+        modes.join(", ")
+    }
+}
+
+/// A joined Twitch chat and the formatted messages received for it.
+pub struct Channel {
+    pub name: String,
+    pub messages: Scrollback,
+    /// Logins of the users who have chatted here, most recent first.
+    pub chatters: VecDeque<String>,
+    /// The chatters above by login, for `:whois`.
+    pub chatter_info: HashMap<String, ChatterInfo>,
+    /// Whether we are a moderator or the broadcaster here.
+    pub moderator: bool,
+    /// Whether we are a VIP here, who slow mode doesn't hold back either.
+    pub vip: bool,
+    pub room_state: RoomState,
+    /// When our last message was sent, or will be once slow mode lets it.
+    last_sent: Option<Instant>,
+    /// Logins of everyone in the chat, as far as joins, parts, the list
+    /// Twitch sends on joining and who has chatted tell.
+    pub viewers: BTreeSet<String>,
+    /// Whether the viewer list is shown next to the chat.
+    pub show_viewers: bool,
+    /// Only viewers with this in their name are listed.
+    pub viewer_filter: Option<String>,
+}
+
+impl Channel {
+    pub fn new(name: String, history_size: usize) -> Channel {
+        Channel {
+            name,
+            messages: Scrollback::new(history_size),
+            chatters: VecDeque::new(),
+            chatter_info: HashMap::new(),
+            moderator: false,
+            vip: false,
+            room_state: RoomState::default(),
+            last_sent: None,
+            viewers: BTreeSet::new(),
+            show_viewers: false,
+            viewer_filter: None,
+        }
+    }
+
+    pub fn saw_chatter(&mut self, login: &str) {
+        if let Some(dropped) = push_recent(&mut self.chatters, login) {
+            self.chatter_info.remove(&dropped);
+        }
+        self.viewers.insert(login.to_string());
+    }
+
+    /// Only kept for the chatters in `chatters`, call `saw_chatter` first.
+    pub fn saw_chatter_info(&mut self, login: &str, info: ChatterInfo) {
+        self.chatter_info.insert(login.to_string(), info);
+    }
+
+    /// How long until slow mode lets us send another message, from `now`.
+    pub fn slow_mode_wait(&self, now: Instant) -> Duration {
+        if self.moderator || self.vip {
+            return Duration::ZERO;
+        }
+        match self.last_sent {
+            Some(last_sent) => (last_sent + self.room_state.slow).saturating_duration_since(now),
+            None => Duration::ZERO,
+        }
+    }
+
+    /// Note that a message of ours goes out `at` then.
+    pub fn sent_message(&mut self, at: Instant) {
+        self.last_sent = Some(at);
+    }
+
+    /// What `login` is here, only chatters' badges are known so everyone
+    /// else but the broadcaster is a viewer.
+    pub fn role(&self, login: &str) -> Role {
+        if login == self.name {
+            return Role::Broadcaster;
+        }
+        let badges = match self.chatter_info.get(login) {
+            Some(info) => &info.badges,
+            None => return Role::Viewer,
+        };
+        let has = |name: &str| badges.iter().any(|badge| badge.name == name);
+        if has("broadcaster") {
+            Role::Broadcaster
+        } else if has("moderator") {
+            Role::Moderator
+        } else if has("vip") {
+            Role::Vip
+        } else {
+            Role::Viewer
+        }
+    }
+
+    /// The viewers matching the filter by role, each group sorted by name
+    /// and shown by their display name when they've chatted.
+    pub fn viewer_groups(&self) -> Vec<(Role, Vec<String>)> {
+        let filter = self.viewer_filter.as_deref();
+        let mut groups: Vec<(Role, Vec<String>)> = Vec::new();
+        let mut viewers: Vec<(Role, &String)> = self
+            .viewers
+            .iter()
+            .filter(|login| filter.is_none_or(|filter| login.contains(filter)))
+            .map(|login| (self.role(login), login))
+            .collect();
+        viewers.sort();
+        for (role, login) in viewers {
+            let name = match self.chatter_info.get(login) {
+                Some(info) => info.name.clone(),
+                None => login.clone(),
+            };
+            match groups.last_mut() {
+                Some((last, names)) if *last == role => names.push(name),
+                _ => groups.push((role, vec![name])),
+            }
+        }
+        groups
+    }
+
+    /// Show or hide the viewer list, showing only viewers matching
+    /// `filter` when there is one.
+    pub fn toggle_viewers(&mut self, filter: Option<String>) {
+        self.show_viewers = filter.is_some() || !self.show_viewers;
+        self.viewer_filter = filter.map(|filter| filter.to_lowercase());
+    }
+}
+
+/// Whispers to and from one other user, kept apart from every channel.
+pub struct Conversation {
+    /// The login of who we're whispering with.
+    pub partner: String,
+    pub messages: Scrollback,
+    /// Whispers that arrived while the conversation wasn't on screen.
+    pub unread: usize,
+}
+
+/// What the chat pane shows.
+#[derive(Clone, PartialEq)]
+enum View {
+    /// The active channel's tab, or the home screen without one.
+    Tab,
+    Mentions,
+    /// The conversation with this login.
+    Whisper(String),
+}
+
+/// Every joined channel, one tab each, and the index of the tab on screen.
+pub struct Channels {
+    tabs: Vec<Channel>,
+    active: usize,
+    history_size: usize,
+    /// Messages that arrive while no channel is joined, shown on the home screen.
+    pub notices: Scrollback,
+    /// Every channel joined this session, most recent first.
+    pub recent: VecDeque<String>,
+    /// Highlighted messages from every channel, for the mentions view.
+    pub mentions: Scrollback,
+    /// Mentions that arrived while the mentions view wasn't on screen.
+    unread_mentions: usize,
+    /// Whisper conversations, in the order they started.
+    whispers: Vec<Conversation>,
+    view: View,
+}
+
+impl Channels {
+    /// `history_size` bounds the number of messages kept for each channel.
+    pub fn new(history_size: usize) -> Channels {
+        Channels {
+            tabs: Vec::new(),
+            active: 0,
+            history_size,
+            notices: Scrollback::new(history_size),
+            recent: VecDeque::new(),
+            mentions: Scrollback::new(history_size),
+            unread_mentions: 0,
+            whispers: Vec::new(),
+            view: View::Tab,
+        }
+    }
+
+    /// Open a tab for `name` and switch to it. Joining a channel
+    /// that already has a tab only switches to it.
+    pub fn join(&mut self, name: &str) {
+        let name = name.trim_start_matches('#').to_lowercase();
+        push_recent(&mut self.recent, &name);
+        self.view = View::Tab;
+        match self.position(&name) {
+            Some(index) => self.active = index,
+            None => {
+                self.tabs.push(Channel::new(name, self.history_size));
+                self.active = self.tabs.len() - 1;
+            }
+        }
+    }
+
+    /// Close the tab for `name`, returns false if it was never joined.
+    pub fn part(&mut self, name: &str) -> bool {
+        let name = name.trim_start_matches('#').to_lowercase();
+        match self.position(&name) {
+            Some(index) => {
+                self.tabs.remove(index);
+                if self.active > index || self.active >= self.tabs.len() {
+                    self.active = self.active.saturating_sub(1);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Switch to the tab at `index`, counting from zero.
+    pub fn select(&mut self, index: usize) -> bool {
+        if index < self.tabs.len() {
+            self.active = index;
+            self.view = View::Tab;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn next(&mut self) {
+        self.view = View::Tab;
+        if !self.tabs.is_empty() {
+            self.active = (self.active + 1) % self.tabs.len();
+        }
+    }
+
+    pub fn prev(&mut self) {
+        self.view = View::Tab;
+        if !self.tabs.is_empty() {
+            self.active = (self.active + self.tabs.len() - 1) % self.tabs.len();
+        }
+    }
+
+    /// Show a line from tuitch itself, such as command output or errors,
+    /// in the view on screen or on the home screen.
+    pub fn notify(&mut self, line: String) {
+        if self.view == View::Mentions {
+            self.mentions.push(line);
+            return;
+        }
+        if let Some(conversation) = self.showing_whisper_mut() {
+            conversation.messages.push(line);
+            return;
+        }
+        match self.tabs.get_mut(self.active) {
+            Some(channel) => channel.messages.push(line),
+            None => self.notices.push(line),
+        }
+    }
+
+    /// Keep a copy of a highlighted `line` from `channel` in the mentions
+    /// view, with `marker` naming the channel in front of it.
+    pub fn mention(&mut self, line: &Line, channel: &str, marker: &str) {
+        let mut mention = line.clone();
+        mention.prefix = format!(" {}{}", marker, mention.prefix);
+        mention.channel = Some(channel.to_string());
+        self.mentions.push(mention);
+        if self.view != View::Mentions {
+            self.unread_mentions += 1;
+        }
+    }
+
+    /// Show the mentions view in place of the tab on screen, or go back.
+    pub fn toggle_mentions(&mut self) {
+        self.view = match self.view {
+            View::Mentions => View::Tab,
+            _ => View::Mentions,
+        };
+        self.unread_mentions = 0;
+    }
+
+    pub fn is_showing_mentions(&self) -> bool {
+        self.view == View::Mentions
+    }
+
+    /// Whether the active channel's tab is on screen, not another view.
+    pub fn is_showing_tab(&self) -> bool {
+        self.view == View::Tab
+    }
+
+    pub fn unread_mentions(&self) -> usize {
+        self.unread_mentions
+    }
+
+    /// Add `line` to the conversation with `partner`, starting one if
+    /// there's none yet.
+    pub fn whisper(&mut self, partner: &str, line: Line) {
+        let partner = partner.trim_start_matches('@').to_lowercase();
+        let showing = self.view == View::Whisper(partner.clone());
+        let conversation = self.conversation_mut(&partner);
+        conversation.messages.push(line);
+        if !showing {
+            conversation.unread += 1;
+        }
+    }
+
+    /// Show the conversation with `partner` in place of the tab on screen.
+    pub fn open_whisper(&mut self, partner: &str) {
+        let partner = partner.trim_start_matches('@').to_lowercase();
+        self.conversation_mut(&partner).unread = 0;
+        self.view = View::Whisper(partner);
+    }
+
+    /// Go back from a conversation, or open the first one with unread
+    /// whispers, or else the first one. Returns false without any.
+    pub fn toggle_whispers(&mut self) -> bool {
+        if let View::Whisper(_) = self.view {
+            self.view = View::Tab;
+            return true;
+        }
+        let partner = self
+            .whispers
+            .iter()
+            .find(|conversation| conversation.unread > 0)
+            .or_else(|| self.whispers.first())
+            .map(|conversation| conversation.partner.clone());
+        match partner {
+            Some(partner) => {
+                self.open_whisper(&partner);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The conversation on screen, if it's one.
+    pub fn showing_whisper(&self) -> Option<&Conversation> {
+        match &self.view {
+            View::Whisper(partner) => self
+                .whispers
+                .iter()
+                .find(|conversation| &conversation.partner == partner),
+            _ => None,
+        }
+    }
+
+    fn showing_whisper_mut(&mut self) -> Option<&mut Conversation> {
+        match &self.view {
+            View::Whisper(partner) => self
+                .whispers
+                .iter_mut()
+                .find(|conversation| &conversation.partner == partner),
+            _ => None,
+        }
+    }
+
+    pub fn conversations(&self) -> &[Conversation] {
+        &self.whispers
+    }
+
+    fn conversation_mut(&mut self, partner: &str) -> &mut Conversation {
+        let index = match self
+            .whispers
+            .iter()
+            .position(|conversation| conversation.partner == partner)
+        {
+            Some(index) => index,
+            None => {
+                self.whispers.push(Conversation {
+                    partner: partner.to_string(),
+                    messages: Scrollback::new(self.history_size),
+                    unread: 0,
+                });
+                self.whispers.len() - 1
+            }
+        };
+        &mut self.whispers[index]
+    }
+
+    /// The messages on screen: the mentions view, a conversation or the
+    /// active tab's.
+    pub fn view_mut(&mut self) -> Option<&mut Scrollback> {
+        match self.view {
+            View::Tab => self.active_mut().map(|channel| &mut channel.messages),
+            View::Mentions => Some(&mut self.mentions),
+            View::Whisper(_) => self
+                .showing_whisper_mut()
+                .map(|conversation| &mut conversation.messages),
+        }
+    }
+
+    pub fn active(&self) -> Option<&Channel> {
+        self.tabs.get(self.active)
+    }
+
+    pub fn active_mut(&mut self) -> Option<&mut Channel> {
+        self.tabs.get_mut(self.active)
+    }
+
+    pub fn active_index(&self) -> usize {
+        self.active
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Channel> {
+        self.tabs.iter().find(|channel| channel.name == name)
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Channel> {
+        self.tabs.iter_mut().find(|channel| channel.name == name)
+    }
+
+    pub fn tabs(&self) -> &[Channel] {
+        &self.tabs
+    }
+
+    /// The set of channel logins handed to `set_wanted_channels`.
+    pub fn names(&self) -> HashSet<String> {
+        self.tabs.iter().map(|channel| channel.name.clone()).collect()
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.tabs.iter().position(|channel| channel.name == name)
+    }
+}
+
+/// Move `name` to the front of `list`, dropping the oldest name once full.
+/// Returns the name dropped, if any.
+fn push_recent(list: &mut VecDeque<String>, name: &str) -> Option<String> {
+    match list.iter().position(|entry| entry == name) {
+        Some(index) => {
+            list.remove(index);
+        }
+        None if list.len() >= RECENT_SIZE => {
+            list.push_front(name.to_string());
+            return list.pop_back();
+        }
+        None => {}
+    }
+    list.push_front(name.to_string());
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn joined(names: &[&str], active: usize) -> Channels {
+        let mut channels = Channels::new(10);
+        for name in names {
+            channels.join(name);
+        }
+        assert!(channels.select(active));
+        channels
+    }
+
+    fn active_name(channels: &Channels) -> Option<&str> {
+        channels.active().map(|channel| channel.name.as_str())
+    }
+
+    #[test]
+    fn joining_switches_to_the_tab() {
+        let mut channels = Channels::new(10);
+        channels.join("#Foo");
+        channels.join("bar");
+        assert_eq!(active_name(&channels), Some("bar"));
+        channels.join("foo");
+        assert_eq!(channels.tabs().len(), 2);
+        assert_eq!(active_name(&channels), Some("foo"));
+        assert!(!channels.select(2));
+        assert_eq!(active_name(&channels), Some("foo"));
+    }
+
+    #[test]
+    fn parting_the_active_tab_shows_the_next_one() {
+        let mut channels = joined(&["a", "b", "c"], 1);
+        assert!(channels.part("#B"));
+        assert_eq!(active_name(&channels), Some("c"));
+        assert!(!channels.part("b"));
+    }
+
+    #[test]
+    fn parting_a_tab_before_the_active_one_keeps_it_on_screen() {
+        let mut channels = joined(&["a", "b", "c"], 2);
+        channels.part("a");
+        assert_eq!(active_name(&channels), Some("c"));
+        assert_eq!(channels.active_index(), 1);
+        channels.part("c");
+        assert_eq!(active_name(&channels), Some("b"));
+    }
+
+    #[test]
+    fn parting_the_last_tab() {
+        let mut channels = joined(&["a", "b"], 1);
+        channels.part("b");
+        assert_eq!(active_name(&channels), Some("a"));
+        channels.part("a");
+        assert_eq!(active_name(&channels), None);
+        assert_eq!(channels.active_index(), 0);
+        channels.join("d");
+        assert_eq!(active_name(&channels), Some("d"));
+    }
+
+    #[test]
+    fn keeps_the_most_recent_chatters() {
+        let mut channel = Channel::new("xqc".to_string(), 10);
+        let see = |channel: &mut Channel, login: &str| {
+            channel.saw_chatter(login);
+            channel.saw_chatter_info(
+                login,
+                ChatterInfo {
+                    name: login.to_string(),
+                    badges: Vec::new(),
+                    badge_info: Vec::new(),
+                },
+            );
+        };
+        for index in 0..RECENT_SIZE {
+            see(&mut channel, &format!("user{}", index));
+        }
+        see(&mut channel, "user0");
+        see(&mut channel, "new");
+        assert_eq!(channel.chatters.len(), RECENT_SIZE);
+        assert_eq!(channel.chatter_info.len(), RECENT_SIZE);
+        assert_eq!(channel.chatters.front().map(String::as_str), Some("new"));
+        assert_eq!(channel.chatters[1], "user0");
+        assert!(!channel.chatter_info.contains_key("user1"));
+        assert!(channel.chatter_info.contains_key("user0"));
+        assert!(channel.viewers.contains("user1"));
+    }
+}
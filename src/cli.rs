@@ -0,0 +1,52 @@
+use lexopt::prelude::*;
+use std::{path::PathBuf, process};
+
+const USAGE: &str = "\
+Usage: tuitch [options]
+
+Options:
+  -c, --config <path>     Read the config from <path> instead of
+                          $TUITCH_CONFIG or $XDG_CONFIG_HOME/tuitch/config.toml
+  -j, --channel <name>    Join <name>'s chat on start, can be given more than once
+  -a, --anonymous         Read chat without logging in
+  -V, --version           Print the version and exit
+  -h, --help              Print this help and exit
+";
+
+/// What was asked for on the command line.
+#[derive(Default)]
+pub struct Args {
+    pub config: Option<PathBuf>,
+    pub channels: Vec<String>,
+    pub anonymous: bool,
+}
+
+/// Parse the command line, `--help` and `--version` are answered here
+/// and end the process.
+pub fn parse_args() -> Result<Args, lexopt::Error> {
+    let mut args = Args::default();
+    let mut parser = lexopt::Parser::from_env();
+    while let Some(arg) = parser.next()? {
+        match arg {
+            Short('c') | Long("config") => args.config = Some(parser.value()?.into()),
+            Short('j') | Long("channel") => args.channels.push(parser.value()?.string()?),
+            Short('a') | Long("anonymous") => args.anonymous = true,
+            Short('V') | Long("version") => {
+                println!("tuitch {}", env!("CARGO_PKG_VERSION"));
+                process::exit(0);
+            }
+            Short('h') | Long("help") => {
+                print!("{}", USAGE);
+                process::exit(0);
+            }
+            _ => return Err(arg.unexpected()),
+        }
+    }
+    Ok(args)
+}
+
+/// Print what's wrong with the command line and exit.
+pub fn usage_error(error: lexopt::Error) -> ! {
+    eprintln!("tuitch: {}\n\n{}", error, USAGE);
+    process::exit(2);
+}
@@ -1,76 +1,1416 @@
-use crate::user_config::{create_config_file, UserConfig};
-use crate::user_interface::reset_screen;
-use std::{collections::HashSet, sync::Arc};
-use tokio::sync::RwLock;
-use twitch_irc::{
-    login::StaticLoginCredentials, /*ClientConfig,*/ SecureTCPTransport, TwitchIRCClient,
-};
+use crate::badges::describe_badges;
+use crate::channels::Channels;
+use crate::credentials::{self, oauth_token, secret_path, Prompt};
+use crate::ignore::Ignores;
+use crate::input::InputBuffer;
+use crate::messages::{send_user_message, send_whisper};
+use crate::moderation::{moderate, parse_duration, ModAction, Setting};
+use crate::theme::{Theme, Themes};
+use crate::user_config::{create_config_file, edit_config_file, ConfigError, UserConfig};
+use crate::user_interface::{Connection, Status};
+use crate::{Client, IncomingMessages};
+use owo_colors::OwoColorize;
+use std::{fmt, path::Path, sync::Arc};
+use tokio::sync::{mpsc::UnboundedSender, RwLock};
+use twitch_irc::{irc, login::StaticLoginCredentials, ClientConfig};
 
+/// What a command argument accepts.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ArgKind {
+    /// A single word.
+    Word,
+    /// A whole number.
+    Number,
+    /// A duration like `10m` or `1h30m`, see `parse_duration`.
+    Duration,
+    /// `on` or `off`.
+    Switch,
+    /// `on`, `off` or a duration.
+    Setting,
+    /// The rest of the line, spaces and all. Only the last argument can be text.
+    Text,
+}
+
+pub struct ArgSpec {
+    pub name: &'static str,
+    pub kind: ArgKind,
+    pub required: bool,
+}
+
+const fn required(name: &'static str, kind: ArgKind) -> ArgSpec {
+    ArgSpec {
+        name,
+        kind,
+        required: true,
+    }
+}
+
+const fn optional(name: &'static str, kind: ArgKind) -> ArgSpec {
+    ArgSpec {
+        name,
+        kind,
+        required: false,
+    }
+}
+
+/// A command the user can run from the input line, everything `:help`
+/// knows about it and how its checked arguments become a `Command`.
+pub struct CommandSpec {
+    pub name: &'static str,
+    pub aliases: &'static [&'static str],
+    pub args: &'static [ArgSpec],
+    pub help: &'static str,
+    build: fn(Vec<String>) -> Command,
+}
+
+impl ArgKind {
+    /// Whether `value` is one this kind of argument accepts.
+    fn accepts(&self, value: &str) -> bool {
+        match self {
+            ArgKind::Word | ArgKind::Text => true,
+            ArgKind::Number => value.parse::<usize>().is_ok(),
+            ArgKind::Duration => parse_duration(value).is_some(),
+            ArgKind::Switch => switch(value).is_some(),
+            ArgKind::Setting => Setting::parse(value).is_some(),
+        }
+    }
+
+    /// What it accepts, to say so when it's given something else.
+    fn expected(&self) -> &'static str {
+        match self {
+            ArgKind::Word | ArgKind::Text => "a word",
+            ArgKind::Number => "a number",
+            ArgKind::Duration => "a duration like 10m or 1h30m",
+            ArgKind::Switch => "on or off",
+            ArgKind::Setting => "on, off or a duration like 10m",
+        }
+    }
+}
+
+/// `on` or `off` as a bool, anything else is `None`.
+fn switch(value: &str) -> Option<bool> {
+    match value.to_lowercase().as_str() {
+        "on" => Some(true),
+        "off" => Some(false),
+        _ => None,
+    }
+}
+
+impl CommandSpec {
+    /// The command as it's typed, e.g. `:part [channel]`.
+    pub fn usage(&self) -> String {
+        let mut usage = format!(":{}", self.name);
+        for arg in self.args {
+            if arg.required {
+                usage.push_str(&format!(" <{}>", arg.name));
+            } else {
+                usage.push_str(&format!(" [{}]", arg.name));
+            }
+        }
+        usage
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        self.name == name || self.aliases.contains(&name)
+    }
+}
+
+impl PartialEq for CommandSpec {
+    fn eq(&self, other: &CommandSpec) -> bool {
+        self.name == other.name
+    }
+}
+
+impl fmt::Debug for CommandSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, ":{}", self.name)
+    }
+}
+
+/// Every built-in command, in the order `:help` lists them.
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        name: "join",
+        aliases: &["j"],
+        args: &[required("channel", ArgKind::Word)],
+        help: "Join a channel's chat in a new tab",
+        build: |args| Command::Join(args[0].clone()),
+    },
+    CommandSpec {
+        name: "part",
+        aliases: &["leave"],
+        args: &[optional("channel", ArgKind::Word)],
+        help: "Leave a channel, the one on screen if none is given",
+        build: |args| Command::Part(args.first().cloned()),
+    },
+    CommandSpec {
+        name: "tab",
+        aliases: &["t"],
+        args: &[required("number", ArgKind::Number)],
+        help: "Switch to a tab by its number",
+        build: |args| Command::Tab(args[0].parse().unwrap_or_default()),
+    },
+    CommandSpec {
+        name: "next",
+        aliases: &["n"],
+        args: &[],
+        help: "Switch to the next tab",
+        build: |_| Command::Next,
+    },
+    CommandSpec {
+        name: "prev",
+        aliases: &["p"],
+        args: &[],
+        help: "Switch to the previous tab",
+        build: |_| Command::Prev,
+    },
+    CommandSpec {
+        name: "login",
+        aliases: &[],
+        args: &[
+            required("username", ArgKind::Word),
+            optional("oauth token", ArgKind::Word),
+        ],
+        help: "Log in as another Twitch user, the token is asked for if left out",
+        build: |args| Command::Login {
+            username: args[0].clone(),
+            token: args.get(1).cloned(),
+        },
+    },
+    CommandSpec {
+        name: "logout",
+        aliases: &[],
+        args: &[],
+        help: "Log out and keep reading chat anonymously",
+        build: |_| Command::Logout,
+    },
+    CommandSpec {
+        name: "credentials",
+        aliases: &[],
+        args: &[optional("username", ArgKind::Word)],
+        help: "Save a Twitch login, asking for its token without showing it",
+        build: |args| Command::Credentials(args.first().cloned()),
+    },
+    CommandSpec {
+        name: "reply",
+        aliases: &["r"],
+        args: &[
+            required("msg-id|@user", ArgKind::Word),
+            required("message", ArgKind::Text),
+        ],
+        help: "Reply to a message, or to someone's last message in the chat on screen",
+        build: |args| Command::Reply {
+            to: args[0].clone(),
+            message: args[1].clone(),
+        },
+    },
+    CommandSpec {
+        name: "me",
+        aliases: &[],
+        args: &[required("message", ArgKind::Text)],
+        help: "Send an action to the chat on screen, like /me",
+        build: |args| Command::Me(args[0].clone()),
+    },
+    CommandSpec {
+        name: "whois",
+        aliases: &[],
+        args: &[required("user", ArgKind::Word)],
+        help: "Show a chatter's badges in the chat on screen, with their sub months",
+        build: |args| Command::Whois(args[0].clone()),
+    },
+    CommandSpec {
+        name: "timeout",
+        aliases: &["to"],
+        args: &[
+            required("user", ArgKind::Word),
+            required("duration", ArgKind::Duration),
+            optional("reason", ArgKind::Text),
+        ],
+        help: "Time a chatter out, for a duration like 10m or 1h30m",
+        build: |args| {
+            Command::Moderate(ModAction::Timeout {
+                user: login(&args[0]),
+                duration: parse_duration(&args[1]).unwrap_or_default(),
+                reason: args.get(2).cloned(),
+            })
+        },
+    },
+    CommandSpec {
+        name: "ban",
+        aliases: &[],
+        args: &[
+            required("user", ArgKind::Word),
+            optional("reason", ArgKind::Text),
+        ],
+        help: "Ban a chatter from the channel on screen",
+        build: |args| {
+            Command::Moderate(ModAction::Ban {
+                user: login(&args[0]),
+                reason: args.get(1).cloned(),
+            })
+        },
+    },
+    CommandSpec {
+        name: "unban",
+        aliases: &["untimeout"],
+        args: &[required("user", ArgKind::Word)],
+        help: "Lift a chatter's ban or timeout",
+        build: |args| Command::Moderate(ModAction::Unban(login(&args[0]))),
+    },
+    CommandSpec {
+        name: "delete",
+        aliases: &[],
+        args: &[required("msg-id", ArgKind::Word)],
+        help: "Delete a message by its id",
+        build: |args| Command::Moderate(ModAction::Delete(args[0].clone())),
+    },
+    CommandSpec {
+        name: "slow",
+        aliases: &[],
+        args: &[optional("on|off|duration", ArgKind::Setting)],
+        help: "Turn slow mode on or off, or set how long chatters wait between messages",
+        build: |args| Command::Moderate(ModAction::Slow(setting(args.first()))),
+    },
+    CommandSpec {
+        name: "followers",
+        aliases: &[],
+        args: &[optional("on|off|duration", ArgKind::Setting)],
+        help: "Only let followers chat, or those who followed at least this long ago",
+        build: |args| Command::Moderate(ModAction::Followers(setting(args.first()))),
+    },
+    CommandSpec {
+        name: "emoteonly",
+        aliases: &[],
+        args: &[optional("on|off", ArgKind::Switch)],
+        help: "Turn emote-only mode on or off",
+        build: |args| Command::Moderate(ModAction::EmoteOnly(on(args.first()))),
+    },
+    CommandSpec {
+        name: "subscribers",
+        aliases: &["subs"],
+        args: &[optional("on|off", ArgKind::Switch)],
+        help: "Turn subscribers-only mode on or off",
+        build: |args| Command::Moderate(ModAction::Subscribers(on(args.first()))),
+    },
+    CommandSpec {
+        name: "clear",
+        aliases: &[],
+        args: &[],
+        help: "Clear the chat on screen for everyone",
+        build: |_| Command::Moderate(ModAction::Clear),
+    },
+    CommandSpec {
+        name: "users",
+        aliases: &["viewers"],
+        args: &[optional("filter", ArgKind::Word)],
+        help: "Show or hide who's in the chat on screen, or only those matching a filter",
+        build: |args| Command::Users(args.first().cloned()),
+    },
+    CommandSpec {
+        name: "ignore",
+        aliases: &[],
+        args: &[required("user", ArgKind::Word)],
+        help: "Hide a chatter's messages from now on, in every channel",
+        build: |args| Command::Ignore(args[0].clone()),
+    },
+    CommandSpec {
+        name: "unignore",
+        aliases: &[],
+        args: &[required("user", ArgKind::Word)],
+        help: "Show an ignored chatter's messages again",
+        build: |args| Command::Unignore(args[0].clone()),
+    },
+    CommandSpec {
+        name: "ignored",
+        aliases: &[],
+        args: &[],
+        help: "List the ignored chatters",
+        build: |_| Command::Ignored,
+    },
+    CommandSpec {
+        name: "mentions",
+        aliases: &["m"],
+        args: &[],
+        help: "Show the highlighted messages from every channel, or go back",
+        build: |_| Command::Mentions,
+    },
+    CommandSpec {
+        name: "whisper",
+        aliases: &["w"],
+        args: &[
+            required("user", ArgKind::Word),
+            optional("message", ArgKind::Text),
+        ],
+        help: "Whisper to a user, or open your conversation with them",
+        build: |args| Command::Whisper {
+            to: login(&args[0]),
+            message: args.get(1).cloned(),
+        },
+    },
+    CommandSpec {
+        name: "whispers",
+        aliases: &[],
+        args: &[],
+        help: "Open the conversation with unread whispers, or go back",
+        build: |_| Command::Whispers,
+    },
+    CommandSpec {
+        name: "theme",
+        aliases: &[],
+        args: &[optional("name", ArgKind::Word)],
+        help: "Switch to another theme, or list them",
+        build: |args| Command::Theme(args.first().cloned()),
+    },
+    CommandSpec {
+        name: "help",
+        aliases: &["h", "?"],
+        args: &[optional("command", ArgKind::Word)],
+        help: "List the commands, or show how to use one",
+        build: |args| Command::Help(args.first().cloned()),
+    },
+];
+
+/// `@Name` as the login Twitch knows the chatter by.
+fn login(user: &str) -> String {
+    user.trim_start_matches('@').to_lowercase()
+}
+
+/// A checked `on|off|duration` argument, on when it's left out.
+fn setting(value: Option<&String>) -> Setting {
+    value.and_then(|value| Setting::parse(value)).unwrap_or(Setting::On)
+}
+
+/// A checked `on|off` argument, on when it's left out.
+fn on(value: Option<&String>) -> bool {
+    value.and_then(|value| switch(value)).unwrap_or(true)
+}
+
+/// A parsed command line, ready to run.
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Join(String),
+    Part(Option<String>),
+    Tab(usize),
+    Next,
+    Prev,
+    Login {
+        username: String,
+        token: Option<String>,
+    },
+    Logout,
+    Credentials(Option<String>),
+    Reply {
+        to: String,
+        message: String,
+    },
+    Me(String),
+    Whois(String),
+    Moderate(ModAction),
+    Users(Option<String>),
+    Ignore(String),
+    Unignore(String),
+    Ignored,
+    Mentions,
+    Whisper {
+        to: String,
+        message: Option<String>,
+    },
+    Whispers,
+    Theme(Option<String>),
+    Help(Option<String>),
+}
+
+/// Why a command line couldn't be parsed, shown to the user in place
+/// of running the command.
+#[derive(Debug, PartialEq)]
+pub enum CommandError {
+    Unknown(String),
+    MissingArgument {
+        spec: &'static CommandSpec,
+        argument: &'static str,
+    },
+    TooManyArguments(&'static CommandSpec),
+    InvalidNumber {
+        spec: &'static CommandSpec,
+        argument: &'static str,
+        value: String,
+    },
+    InvalidValue {
+        spec: &'static CommandSpec,
+        argument: &'static str,
+        kind: ArgKind,
+        value: String,
+    },
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommandError::Unknown(name) => {
+                write!(f, "Unknown command :{}, enter :help for a list of commands", name)
+            }
+            CommandError::MissingArgument { spec, argument } => {
+                write!(f, "Missing <{}>, usage: {}", argument, spec.usage())
+            }
+            CommandError::TooManyArguments(spec) => {
+                write!(f, "Too many arguments, usage: {}", spec.usage())
+            }
+            CommandError::InvalidNumber {
+                spec,
+                argument,
+                value,
+            } => write!(
+                f,
+                "<{}> must be a number, not {}, usage: {}",
+                argument,
+                value,
+                spec.usage()
+            ),
+            CommandError::InvalidValue {
+                spec,
+                argument,
+                kind,
+                value,
+            } => write!(
+                f,
+                "<{}> must be {}, not {}, usage: {}",
+                argument,
+                kind.expected(),
+                value,
+                spec.usage()
+            ),
+        }
+    }
+}
+
+/// Look up a command by its name or one of its aliases, with or without the ':'.
+pub fn find_command(name: &str) -> Option<&'static CommandSpec> {
+    let name = name.strip_prefix(':').unwrap_or(name);
+    COMMANDS.iter().find(|spec| spec.matches(name))
+}
+
+/// Check a command line against its command's arguments.
+pub fn parse_command(line: &str) -> Result<Command, CommandError> {
+    let line = line.trim();
+    let line = line.strip_prefix(':').unwrap_or(line);
+    let (name, mut rest) = match line.find(char::is_whitespace) {
+        Some(index) => (&line[..index], line[index..].trim_start()),
+        None => (line, ""),
+    };
+    let spec = find_command(name).ok_or_else(|| CommandError::Unknown(name.to_string()))?;
+
+    let mut values = Vec::new();
+    for arg in spec.args {
+        if rest.is_empty() {
+            if arg.required {
+                return Err(CommandError::MissingArgument {
+                    spec,
+                    argument: arg.name,
+                });
+            }
+            break;
+        }
+        let end = match arg.kind {
+            ArgKind::Text => rest.len(),
+            _ => rest.find(char::is_whitespace).unwrap_or(rest.len()),
+        };
+        let (value, remainder) = rest.split_at(end);
+        rest = remainder.trim_start();
+        if arg.kind == ArgKind::Number && value.parse::<usize>().is_err() {
+            return Err(CommandError::InvalidNumber {
+                spec,
+                argument: arg.name,
+                value: value.to_string(),
+            });
+        }
+        if !arg.kind.accepts(value) {
+            return Err(CommandError::InvalidValue {
+                spec,
+                argument: arg.name,
+                kind: arg.kind,
+                value: value.to_string(),
+            });
+        }
+        values.push(value.to_string());
+    }
+    if !rest.is_empty() {
+        return Err(CommandError::TooManyArguments(spec));
+    }
+    Ok((spec.build)(values))
+}
+
+/// Parse and run a command line, any problem with it is shown
+/// in the tab on screen instead.
+#[allow(clippy::too_many_arguments)]
 pub async fn run_command(
-    input_buffer: Arc<RwLock<String>>,
-    current_channel: Arc<RwLock<String>>,
-    config_path: &str,
-    client: &TwitchIRCClient<SecureTCPTransport, StaticLoginCredentials>,
+    input: String,
+    channels: Arc<RwLock<Channels>>,
+    status: Arc<RwLock<Status>>,
+    input_buffer: Arc<RwLock<InputBuffer>>,
+    themes: Arc<RwLock<Themes>>,
+    ignores: Arc<RwLock<Ignores>>,
+    config: &UserConfig,
+    client: Arc<RwLock<Client>>,
+    reconnect: &UnboundedSender<IncomingMessages>,
+) {
+    let command = match parse_command(&input) {
+        Ok(command) => command,
+        Err(error) => {
+            channels.write().await.notify(error.to_string().red().to_string());
+            return;
+        }
+    };
+
+    match command {
+        Command::Join(channel) => {
+            join_command(&channel, Arc::clone(&channels), &current(&client).await).await
+        }
+        Command::Part(channel) => {
+            part_command(channel, Arc::clone(&channels), &current(&client).await).await
+        }
+        // Tabs are numbered from 1 on screen.
+        Command::Tab(number) => {
+            let mut channels = channels.write().await;
+            if !number.checked_sub(1).is_some_and(|index| channels.select(index)) {
+                channels.notify(format!("There is no tab {}", number).red().to_string());
+            }
+        }
+        Command::Next => channels.write().await.next(),
+        Command::Prev => channels.write().await.prev(),
+        Command::Login {
+            username,
+            token: Some(token),
+        } => {
+            let login = StaticLoginCredentials::new(username.to_lowercase(), Some(oauth_token(&token)));
+            login_command(login, channels, status, client, reconnect).await;
+        }
+        Command::Login {
+            username,
+            token: None,
+        } => input_buffer.write().await.ask(Prompt::Token {
+            username: username.to_lowercase(),
+            save: false,
+        }),
+        Command::Logout => {
+            let login = StaticLoginCredentials::anonymous();
+            login_command(login, channels, status, client, reconnect).await;
+        }
+        Command::Credentials(username) => {
+            let username = match username {
+                Some(username) => username.to_lowercase(),
+                None => status.read().await.login.clone(),
+            };
+            if username.is_empty() {
+                let error = CommandError::MissingArgument {
+                    spec: find_command("credentials").unwrap(),
+                    argument: "username",
+                };
+                channels.write().await.notify(error.to_string().red().to_string());
+                return;
+            }
+            input_buffer
+                .write()
+                .await
+                .ask(Prompt::Token { username, save: true });
+        }
+        Command::Reply { to, message } => {
+            let username = status.read().await.login.clone();
+            let theme = themes.read().await.current().clone();
+            let client = current(&client).await;
+            reply_command(&username, &to, message, channels, &client, config, &theme).await
+        }
+        Command::Me(message) => {
+            let username = status.read().await.login.clone();
+            let theme = themes.read().await.current().clone();
+            let message = format!("/me {}", message);
+            let client = current(&client).await;
+            send_user_message(&username, channels, message, &client, config, &theme, None).await
+        }
+        Command::Whois(user) => whois_command(&user, channels).await,
+        Command::Moderate(action) => {
+            let username = status.read().await.login.clone();
+            moderate(action, &username, channels, &current(&client).await).await
+        }
+        Command::Users(filter) => {
+            let mut channels = channels.write().await;
+            match channels.active_mut() {
+                Some(channel) => channel.toggle_viewers(filter),
+                None => channels.notify("Join a channel to see who's in it.".red().to_string()),
+            }
+        }
+        Command::Ignore(user) => ignore_command(&user, true, channels, ignores).await,
+        Command::Unignore(user) => ignore_command(&user, false, channels, ignores).await,
+        Command::Ignored => ignored_command(channels, ignores).await,
+        Command::Mentions => channels.write().await.toggle_mentions(),
+        Command::Whisper { to, message: None } => channels.write().await.open_whisper(&to),
+        Command::Whisper {
+            to,
+            message: Some(message),
+        } => {
+            let username = status.read().await.login.clone();
+            let theme = themes.read().await.current().clone();
+            let client = current(&client).await;
+            send_whisper(&username, &to, message, channels, &client, config, &theme).await
+        }
+        Command::Whispers => whispers_command(channels).await,
+        Command::Theme(name) => theme_command(name, channels, themes).await,
+        Command::Help(command) => help_command(command, Arc::clone(&channels)).await,
+    }
+}
+
+/// The client in use, taken out of its lock so the lock isn't held while
+/// a send waits for the connection and `:login` can replace it meanwhile.
+pub async fn current(client: &RwLock<Client>) -> Client {
+    client.read().await.clone()
+}
+
+/// Reply to the message with the id `to`, or to the last message from
+/// `@user` in the channel on screen.
+pub async fn reply_command(
+    username: &str,
+    to: &str,
+    message: String,
+    channels: Arc<RwLock<Channels>>,
+    client: &Client,
+    config: &UserConfig,
+    theme: &Theme,
+) {
+    let parent = match to.strip_prefix('@') {
+        Some(user) => {
+            let login = user.to_lowercase();
+            let parent = channels
+                .read()
+                .await
+                .active()
+                .and_then(|channel| channel.messages.last_from(&login))
+                .and_then(|line| line.message_id.clone());
+            match parent {
+                Some(parent) => parent,
+                None => {
+                    let error = format!("There is no message from {} to reply to.", login);
+                    channels.write().await.notify(error.red().to_string());
+                    return;
+                }
+            }
+        }
+        // A message scrolled out of the chat can still be replied to by its id.
+        None => to.to_string(),
+    };
+    send_user_message(username, channels, message, client, config, theme, Some(parent)).await;
+}
+
+/// Open the conversation with unread whispers, or go back from one.
+pub async fn whispers_command(channels: Arc<RwLock<Channels>>) {
+    let mut channels = channels.write().await;
+    if !channels.toggle_whispers() {
+        channels.notify("No whispers yet, enter :w <user> <message> to send one.".to_string());
+    }
+}
+
+/// Show what the last message from `user` in the channel on screen said
+/// about them.
+pub async fn whois_command(user: &str, channels: Arc<RwLock<Channels>>) {
+    let mut channels = channels.write().await;
+    let login = user.trim_start_matches('@').to_lowercase();
+    let notice = match channels
+        .active()
+        .and_then(|channel| channel.chatter_info.get(&login))
+    {
+        Some(info) => format!(
+            "{}: {}",
+            info.name.bold(),
+            describe_badges(&info.badges, &info.badge_info)
+        ),
+        None => format!("{} hasn't chatted here yet.", login)
+            .red()
+            .to_string(),
+    };
+    channels.notify(notice);
+}
+
+/// Start or stop ignoring `user`, saving the ignored chatters to the
+/// config file.
+pub async fn ignore_command(
+    user: &str,
+    ignore: bool,
+    channels: Arc<RwLock<Channels>>,
+    ignores: Arc<RwLock<Ignores>>,
+) {
+    let mut ignores = ignores.write().await;
+    let login = user.trim_start_matches('@').to_lowercase();
+    let changed = if ignore {
+        ignores.ignore(&login)
+    } else {
+        ignores.unignore(&login)
+    };
+    let notice = match (changed, ignore) {
+        (false, true) => format!("{} is already ignored.", login),
+        (false, false) => format!("{} isn't ignored.", login),
+        (true, _) => match ignores.save().await {
+            Ok(()) if ignore => format!("Ignoring {}.", login),
+            Ok(()) => format!("No longer ignoring {}.", login),
+            Err(error) => format!("{} Changed for this session only.", error)
+                .red()
+                .to_string(),
+        },
+    };
+    drop(ignores);
+    channels.write().await.notify(notice);
+}
+
+/// List the ignored chatters and how many patterns hide messages.
+pub async fn ignored_command(channels: Arc<RwLock<Channels>>, ignores: Arc<RwLock<Ignores>>) {
+    let ignores = ignores.read().await;
+    let users: Vec<&str> = ignores.users().map(String::as_str).collect();
+    let mut notice = match users.len() {
+        0 => "Nobody is ignored.".to_string(),
+        _ => format!("Ignored: {}", users.join(", ")),
+    };
+    match ignores.patterns().len() {
+        0 => {}
+        1 => notice.push_str(" 1 pattern from the config hides messages too."),
+        patterns => notice.push_str(&format!(
+            " {} patterns from the config hide messages too.",
+            patterns
+        )),
+    }
+    drop(ignores);
+    channels.write().await.notify(notice);
+}
+
+/// Switch to the theme called `name`, or list the themes when none is given.
+pub async fn theme_command(
+    name: Option<String>,
+    channels: Arc<RwLock<Channels>>,
+    themes: Arc<RwLock<Themes>>,
 ) {
-    let mut buffer = input_buffer.write().await;
-    let mut command = buffer.split_whitespace();
+    let mut themes = themes.write().await;
+    let notice = match name {
+        None => {
+            let names: Vec<String> = themes
+                .names()
+                .into_iter()
+                .map(|name| {
+                    if name == themes.name() {
+                        format!("{} (current)", name)
+                    } else {
+                        name
+                    }
+                })
+                .collect();
+            format!("Themes: {}", names.join(", "))
+        }
+        Some(name) => match themes.switch(&name) {
+            Ok(()) => format!("Switched to the {} theme.", themes.name()),
+            Err(error) => error.to_string().red().to_string(),
+        },
+    };
+    drop(themes);
+    channels.write().await.notify(notice);
+}
+
+/// Open a tab for `channel` and add it to the client's wanted channels,
+/// every channel joined before it stays joined.
+pub async fn join_command(channel: &str, channels: Arc<RwLock<Channels>>, client: &Client) {
+    let mut channels = channels.write().await;
+    channels.join(channel);
+    request_membership(client);
+    client.set_wanted_channels(channels.names());
+}
+
+/// Ask Twitch for everyone's joins and parts, and the list of who's in a
+/// channel on joining it, for the viewer list. The client doesn't ask for
+/// them itself, so connections it opens again after losing one only get
+/// them from the next `:join` on.
+pub fn request_membership(client: &Client) {
+    let client = client.clone();
+    // Sending waits for the connection, which may not be up yet.
+    tokio::spawn(async move {
+        client
+            .send_message(irc!["CAP", "REQ", "twitch.tv/membership"])
+            .await
+            .ok();
+    });
+}
+
+/// Leave `channel`, or the channel on screen when none is given.
+pub async fn part_command(channel: Option<String>, channels: Arc<RwLock<Channels>>, client: &Client) {
+    let mut channels = channels.write().await;
+    let channel = match channel {
+        Some(channel) => channel,
+        None => match channels.active() {
+            Some(active) => active.name.clone(),
+            None => return,
+        },
+    };
+    if channels.part(&channel) {
+        client.set_wanted_channels(channels.names());
+    } else {
+        channels.notify(format!("Not in {}'s chat", channel).red().to_string());
+    }
+}
 
-    match command.next() {
-        Some(":join") => {
-            if let Some(channel) = command.next() {
-                join_command(channel.to_string(), current_channel, &client).await;
+/// List every command, or the usage and aliases of one.
+pub async fn help_command(command: Option<String>, channels: Arc<RwLock<Channels>>) {
+    let mut channels = channels.write().await;
+    match command {
+        Some(name) => match find_command(&name) {
+            Some(spec) => {
+                channels.notify(format!("{}  {}", spec.usage().bold(), spec.help));
+                if !spec.aliases.is_empty() {
+                    let aliases: Vec<String> =
+                        spec.aliases.iter().map(|alias| format!(":{}", alias)).collect();
+                    channels.notify(format!("Also: {}", aliases.join(", ")));
+                }
             }
-            buffer.clear();
-            reset_screen();
-        }
-        //        Some(":login") => {
-        //            if let Some(username) = command.next() {
-        //                let oauth_token = command.next();
-        //                login_command(username.to_string(), oauth_token.unwrap().to_string(), &client).await;
-        //            }
-        //        }
-        Some(":credentials") => {
-            if let Some(username) = command.next() {
-                let oauth_token = command.next();
-                credentials_command(
-                    username.to_string(),
-                    oauth_token.unwrap().to_string(),
-                    &config_path,
-                )
-                .await;
+            None => {
+                let error = CommandError::Unknown(name.trim_start_matches(':').to_string());
+                channels.notify(error.to_string().red().to_string());
+            }
+        },
+        None => {
+            channels.notify("Commands:".to_string());
+            for spec in COMMANDS {
+                channels.notify(format!("  {}  {}", spec.usage().bold(), spec.help));
             }
-            buffer.clear();
-            reset_screen();
         }
-        _ => {}
     }
 }
 
-pub async fn join_command(
-    channel: String,
-    current_channel: Arc<RwLock<String>>,
-    client: &TwitchIRCClient<SecureTCPTransport, StaticLoginCredentials>,
+/// Replace the client with one logged in with `login`, joined to every
+/// open channel. Anonymous credentials log out.
+pub async fn login_command(
+    login: StaticLoginCredentials,
+    channels: Arc<RwLock<Channels>>,
+    status: Arc<RwLock<Status>>,
+    client: Arc<RwLock<Client>>,
+    reconnect: &UnboundedSender<IncomingMessages>,
+) {
+    let anonymous = login.credentials.token.is_none();
+    let username = if anonymous {
+        String::new()
+    } else {
+        login.credentials.login.clone()
+    };
+
+    let (incoming_messages, new_client) = Client::new(ClientConfig::new_simple(login));
+    request_membership(&new_client);
+    new_client.set_wanted_channels(channels.read().await.names());
+    // The old client disconnects once it's dropped here.
+    *client.write().await = new_client;
+    reconnect.send(incoming_messages).ok();
+
+    let mut channels = channels.write().await;
+    let mut status = status.write().await;
+    status.login = username;
+    status.connection = Connection::Offline;
+    if anonymous {
+        channels.notify("Logged out, reading chat anonymously.".to_string());
+    } else {
+        channels.notify(format!("Logging in as {}...", status.login));
+    }
+}
+
+/// Go on from a prompt the user answered on the input line, asking for
+/// the next secret or logging in once there's nothing left to ask.
+pub async fn answer_prompt(
+    (prompt, answer): (Prompt, String),
+    channels: Arc<RwLock<Channels>>,
+    status: Arc<RwLock<Status>>,
+    input_buffer: Arc<RwLock<InputBuffer>>,
+    config_path: &Path,
+    client: Arc<RwLock<Client>>,
+    reconnect: &UnboundedSender<IncomingMessages>,
 ) {
-    let mut channel_hash = HashSet::<String>::new();
-    let mut channel_buffer = current_channel.write().await;
-    channel_buffer.clear();
-    channel_buffer.push_str(&channel);
-    channel_hash.insert(channel);
-    client.set_wanted_channels(channel_hash);
-}
-
-// pub async fn login_command(
-//     username: String,
-//     token: String,
-//     client: &TwitchIRCClient<SecureTCPTransport, StaticLoginCredentials>,
-// ) {
-//
-// }
-
-pub async fn credentials_command(new_username: String, token: String, config_path: &str) {
-    let config = UserConfig {
-        username: new_username,
-        oauth_token: token,
+    let (username, token, passphrase) = match prompt {
+        Prompt::FirstRun if matches!(answer.trim(), "y" | "Y" | "yes") => {
+            input_buffer.write().await.ask(Prompt::Username);
+            return;
+        }
+        Prompt::FirstRun => {
+            let notice = match create_config_file(config_path, &UserConfig::default()).await {
+                Ok(()) => format!(
+                    "Saved a new config to {}, reading chat anonymously. :credentials logs in later.",
+                    config_path.display()
+                ),
+                Err(error) => error.to_string().red().to_string(),
+            };
+            channels.write().await.notify(notice);
+            return;
+        }
+        Prompt::Username if answer.trim().is_empty() => {
+            channels
+                .write()
+                .await
+                .notify("No username given, nothing changed.".to_string());
+            return;
+        }
+        Prompt::Username => {
+            input_buffer.write().await.ask(Prompt::Token {
+                username: answer.trim().to_lowercase(),
+                save: true,
+            });
+            return;
+        }
+        Prompt::Token { .. } if answer.trim().is_empty() => {
+            channels
+                .write()
+                .await
+                .notify("No OAuth token given, nothing changed.".to_string());
+            return;
+        }
+        Prompt::Token {
+            username,
+            save: false,
+        } => {
+            let login = StaticLoginCredentials::new(username, Some(oauth_token(&answer)));
+            login_command(login, channels, status, client, reconnect).await;
+            return;
+        }
+        Prompt::Token {
+            username,
+            save: true,
+        } => {
+            let token = oauth_token(&answer);
+            input_buffer
+                .write()
+                .await
+                .ask(Prompt::Passphrase { username, token });
+            return;
+        }
+        Prompt::Passphrase { username, token } if answer.is_empty() => (username, token, None),
+        Prompt::Passphrase { username, token } => {
+            input_buffer.write().await.ask(Prompt::RepeatPassphrase {
+                username,
+                token,
+                passphrase: answer,
+            });
+            return;
+        }
+        Prompt::RepeatPassphrase {
+            username,
+            token,
+            passphrase,
+        } if answer == passphrase => (username, token, Some(passphrase)),
+        Prompt::RepeatPassphrase {
+            username, token, ..
+        } => {
+            channels.write().await.notify(
+                "The passphrases don't match, try again."
+                    .red()
+                    .to_string(),
+            );
+            input_buffer
+                .write()
+                .await
+                .ask(Prompt::Passphrase { username, token });
+            return;
+        }
     };
-    create_config_file(config_path, config).await.unwrap();
+
+    let saved = credentials_command(&username, &token, passphrase.as_deref(), config_path).await;
+    match saved {
+        Ok(()) if passphrase.is_some() => channels
+            .write()
+            .await
+            .notify(format!("Credentials for {} saved, encrypted.", username)),
+        Ok(()) => channels
+            .write()
+            .await
+            .notify(format!("Credentials for {} saved.", username)),
+        Err(error) => channels.write().await.notify(error.to_string().red().to_string()),
+    }
+    let login = StaticLoginCredentials::new(username, Some(token));
+    login_command(login, channels, status, client, reconnect).await;
+}
+
+/// Save `username` to the config file and its token to the secret file.
+pub async fn credentials_command(
+    username: &str,
+    token: &str,
+    passphrase: Option<&str>,
+    config_path: &Path,
+) -> Result<(), ConfigError> {
+    // Keep the rest of the user's settings, only the login changes.
+    // A config that doesn't parse is left for the user to fix.
+    edit_config_file(config_path, |document| {
+        let mut value = toml_edit::Value::from(username);
+        if let Some(old) = document.get("username").and_then(toml_edit::Item::as_value) {
+            *value.decor_mut() = old.decor().clone();
+        }
+        document["username"] = toml_edit::Item::Value(value);
+        Ok(())
+    })
+    .await?;
+    let secret_path = secret_path(config_path);
+    credentials::save(&secret_path, token, passphrase).map_err(|source| ConfigError::Write {
+        path: secret_path,
+        source,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn parses_join() {
+        assert_eq!(parse_command(":join xqc"), Ok(Command::Join("xqc".to_string())));
+        assert_eq!(parse_command(":j  xqc "), Ok(Command::Join("xqc".to_string())));
+        assert_eq!(
+            parse_command(":join"),
+            Err(CommandError::MissingArgument {
+                spec: find_command("join").unwrap(),
+                argument: "channel",
+            })
+        );
+        assert_eq!(
+            parse_command(":join a b"),
+            Err(CommandError::TooManyArguments(find_command("join").unwrap()))
+        );
+    }
+
+    #[test]
+    fn parses_part() {
+        assert_eq!(parse_command(":part"), Ok(Command::Part(None)));
+        assert_eq!(
+            parse_command(":leave xqc"),
+            Ok(Command::Part(Some("xqc".to_string())))
+        );
+    }
+
+    #[test]
+    fn parses_tab() {
+        assert_eq!(parse_command(":tab 2"), Ok(Command::Tab(2)));
+        assert_eq!(parse_command(":t 10"), Ok(Command::Tab(10)));
+        assert_eq!(
+            parse_command(":tab two"),
+            Err(CommandError::InvalidNumber {
+                spec: find_command("tab").unwrap(),
+                argument: "number",
+                value: "two".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn parses_next_and_prev() {
+        assert_eq!(parse_command(":next"), Ok(Command::Next));
+        assert_eq!(parse_command(":prev"), Ok(Command::Prev));
+        assert_eq!(
+            parse_command(":next 1"),
+            Err(CommandError::TooManyArguments(find_command("next").unwrap()))
+        );
+    }
+
+    #[test]
+    fn parses_login_and_logout() {
+        assert_eq!(
+            parse_command(":login user oauth:abc"),
+            Ok(Command::Login {
+                username: "user".to_string(),
+                token: Some("oauth:abc".to_string()),
+            })
+        );
+        assert_eq!(
+            parse_command(":login user"),
+            Ok(Command::Login {
+                username: "user".to_string(),
+                token: None,
+            })
+        );
+        assert_eq!(
+            parse_command(":login"),
+            Err(CommandError::MissingArgument {
+                spec: find_command("login").unwrap(),
+                argument: "username",
+            })
+        );
+        assert_eq!(parse_command(":logout"), Ok(Command::Logout));
+    }
+
+    #[test]
+    fn parses_credentials() {
+        assert_eq!(
+            parse_command(":credentials user"),
+            Ok(Command::Credentials(Some("user".to_string())))
+        );
+        assert_eq!(parse_command(":credentials"), Ok(Command::Credentials(None)));
+        // The token is never typed as part of the command.
+        assert_eq!(
+            parse_command(":credentials user oauth:abc"),
+            Err(CommandError::TooManyArguments(
+                find_command("credentials").unwrap()
+            ))
+        );
+    }
+
+    #[test]
+    fn parses_reply() {
+        assert_eq!(
+            parse_command(":reply @xqc  hello there "),
+            Ok(Command::Reply {
+                to: "@xqc".to_string(),
+                message: "hello there".to_string(),
+            })
+        );
+        assert_eq!(
+            parse_command(":r abc-123"),
+            Err(CommandError::MissingArgument {
+                spec: find_command("reply").unwrap(),
+                argument: "message",
+            })
+        );
+    }
+
+    #[test]
+    fn parses_whois() {
+        assert_eq!(
+            parse_command(":whois xqc"),
+            Ok(Command::Whois("xqc".to_string()))
+        );
+        assert_eq!(
+            parse_command(":whois"),
+            Err(CommandError::MissingArgument {
+                spec: find_command("whois").unwrap(),
+                argument: "user",
+            })
+        );
+    }
+
+    #[test]
+    fn parses_timeout() {
+        assert_eq!(
+            parse_command(":timeout @Spammer 1h30m stop that"),
+            Ok(Command::Moderate(ModAction::Timeout {
+                user: "spammer".to_string(),
+                duration: Duration::from_secs(5400),
+                reason: Some("stop that".to_string()),
+            }))
+        );
+        assert_eq!(
+            parse_command(":to spammer soon"),
+            Err(CommandError::InvalidValue {
+                spec: find_command("timeout").unwrap(),
+                argument: "duration",
+                kind: ArgKind::Duration,
+                value: "soon".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn parses_ban() {
+        assert_eq!(
+            parse_command(":ban @Spammer no bots"),
+            Ok(Command::Moderate(ModAction::Ban {
+                user: "spammer".to_string(),
+                reason: Some("no bots".to_string()),
+            }))
+        );
+        assert_eq!(
+            parse_command(":ban spammer"),
+            Ok(Command::Moderate(ModAction::Ban {
+                user: "spammer".to_string(),
+                reason: None,
+            }))
+        );
+        assert!(matches!(
+            parse_command(":ban"),
+            Err(CommandError::MissingArgument { argument: "user", .. })
+        ));
+    }
+
+    #[test]
+    fn parses_unban() {
+        assert_eq!(
+            parse_command(":unban @Spammer"),
+            Ok(Command::Moderate(ModAction::Unban("spammer".to_string())))
+        );
+        assert_eq!(
+            parse_command(":untimeout spammer"),
+            Ok(Command::Moderate(ModAction::Unban("spammer".to_string())))
+        );
+        assert!(matches!(
+            parse_command(":unban spammer now"),
+            Err(CommandError::TooManyArguments(_))
+        ));
+    }
+
+    #[test]
+    fn parses_delete() {
+        assert_eq!(
+            parse_command(":delete 885196de-cb67-427a-baa8-82f9b0fcd05f"),
+            Ok(Command::Moderate(ModAction::Delete(
+                "885196de-cb67-427a-baa8-82f9b0fcd05f".to_string()
+            )))
+        );
+        assert!(matches!(
+            parse_command(":delete"),
+            Err(CommandError::MissingArgument { argument: "msg-id", .. })
+        ));
+    }
+
+    #[test]
+    fn parses_clear() {
+        assert_eq!(
+            parse_command(":clear"),
+            Ok(Command::Moderate(ModAction::Clear))
+        );
+        assert!(matches!(
+            parse_command(":clear everything"),
+            Err(CommandError::TooManyArguments(_))
+        ));
+    }
+
+    #[test]
+    fn parses_chat_modes() {
+        assert_eq!(
+            parse_command(":slow"),
+            Ok(Command::Moderate(ModAction::Slow(Setting::On)))
+        );
+        assert_eq!(
+            parse_command(":followers 10m"),
+            Ok(Command::Moderate(ModAction::Followers(Setting::For(
+                Duration::from_secs(600)
+            ))))
+        );
+        assert_eq!(
+            parse_command(":emoteonly off"),
+            Ok(Command::Moderate(ModAction::EmoteOnly(false)))
+        );
+        assert_eq!(
+            parse_command(":subs 10m"),
+            Err(CommandError::InvalidValue {
+                spec: find_command("subscribers").unwrap(),
+                argument: "on|off",
+                kind: ArgKind::Switch,
+                value: "10m".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn parses_users() {
+        assert_eq!(parse_command(":users"), Ok(Command::Users(None)));
+        assert_eq!(
+            parse_command(":viewers bot"),
+            Ok(Command::Users(Some("bot".to_string())))
+        );
+    }
+
+    #[test]
+    fn parses_ignore() {
+        assert_eq!(
+            parse_command(":ignore @Nightbot"),
+            Ok(Command::Ignore("@Nightbot".to_string()))
+        );
+        assert_eq!(
+            parse_command(":unignore nightbot"),
+            Ok(Command::Unignore("nightbot".to_string()))
+        );
+        assert_eq!(parse_command(":ignored"), Ok(Command::Ignored));
+        assert_eq!(
+            parse_command(":ignore"),
+            Err(CommandError::MissingArgument {
+                spec: find_command("ignore").unwrap(),
+                argument: "user",
+            })
+        );
+    }
+
+    #[test]
+    fn parses_mentions() {
+        assert_eq!(parse_command(":mentions"), Ok(Command::Mentions));
+        assert_eq!(parse_command(":m"), Ok(Command::Mentions));
+    }
+
+    #[test]
+    fn parses_me() {
+        assert_eq!(
+            parse_command(":me dances"),
+            Ok(Command::Me("dances".to_string()))
+        );
+        assert!(matches!(
+            parse_command(":me"),
+            Err(CommandError::MissingArgument { argument: "message", .. })
+        ));
+    }
+
+    #[test]
+    fn parses_whisper() {
+        assert_eq!(
+            parse_command(":w @Bob hi there"),
+            Ok(Command::Whisper {
+                to: "bob".to_string(),
+                message: Some("hi there".to_string()),
+            })
+        );
+        assert_eq!(
+            parse_command(":whisper bob"),
+            Ok(Command::Whisper {
+                to: "bob".to_string(),
+                message: None,
+            })
+        );
+        assert!(matches!(
+            parse_command(":w"),
+            Err(CommandError::MissingArgument { argument: "user", .. })
+        ));
+    }
+
+    #[test]
+    fn parses_theme() {
+        assert_eq!(parse_command(":theme"), Ok(Command::Theme(None)));
+        assert_eq!(
+            parse_command(":theme light"),
+            Ok(Command::Theme(Some("light".to_string())))
+        );
+    }
+
+    #[test]
+    fn parses_help() {
+        assert_eq!(parse_command(":help"), Ok(Command::Help(None)));
+        assert_eq!(
+            parse_command(":? join"),
+            Ok(Command::Help(Some("join".to_string())))
+        );
+    }
+
+    #[test]
+    fn unknown_command() {
+        assert_eq!(
+            parse_command(":dance now"),
+            Err(CommandError::Unknown("dance".to_string()))
+        );
+    }
+
+    #[test]
+    fn usage_from_arguments() {
+        assert_eq!(find_command("part").unwrap().usage(), ":part [channel]");
+        assert_eq!(
+            find_command(":login").unwrap().usage(),
+            ":login <username> [oauth token]"
+        );
+    }
+
+    #[tokio::test]
+    async fn credentials_only_change_the_username() {
+        let dir = std::env::temp_dir().join(format!("tuitch-credentials-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.toml");
+        let config = "\
+# my settings
+username = \"old\" # me
+theme = \"light\"
+";
+        std::fs::write(&path, config).unwrap();
+        credentials_command("new", "oauth:abc", None, &path).await.unwrap();
+        let saved = std::fs::read_to_string(&path).unwrap();
+        let token = credentials::load(&secret_path(&path)).unwrap();
+        std::fs::remove_dir_all(&dir).ok();
+        assert_eq!(saved, config.replace("\"old\"", "\"new\""));
+        assert!(matches!(token, Some(credentials::Secret::Plain(token)) if token == "abc"));
+    }
 }
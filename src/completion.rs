@@ -0,0 +1,100 @@
+use crate::channels::Channels;
+use crate::commands::{find_command, COMMANDS};
+
+/// What the word before the cursor could be completed to, and the byte
+/// offset it starts at: command names for a leading `:`, channels for
+/// the argument of `:join` or `:part`, and chatters in the channel on
+/// screen for an `@` mention.
+pub fn completions(before_cursor: &str, channels: &Channels) -> Option<(usize, Vec<String>)> {
+    let start = before_cursor
+        .char_indices()
+        .rev()
+        .find(|(_, c)| c.is_whitespace())
+        .map_or(0, |(index, c)| index + c.len_utf8());
+    let word = &before_cursor[start..];
+    let earlier: Vec<&str> = before_cursor[..start].split_whitespace().collect();
+
+    let candidates: Vec<String> = if earlier.is_empty() && word.starts_with(':') {
+        COMMANDS.iter().map(|spec| format!(":{}", spec.name)).collect()
+    } else if word.starts_with('@') {
+        match channels.active() {
+            Some(channel) => channel
+                .chatters
+                .iter()
+                .map(|login| format!("@{}", login))
+                .collect(),
+            None => Vec::new(),
+        }
+    } else if earlier.len() == 1 && earlier[0].starts_with(':') {
+        match find_command(earlier[0]).map(|spec| spec.name) {
+            Some("join") => channels.recent.iter().cloned().collect(),
+            Some("part") => channels
+                .tabs()
+                .iter()
+                .map(|channel| channel.name.clone())
+                .collect(),
+            _ => Vec::new(),
+        }
+    } else {
+        Vec::new()
+    };
+
+    let word = word.to_lowercase();
+    let matching: Vec<String> = candidates
+        .into_iter()
+        .filter(|candidate| candidate.to_lowercase().starts_with(&word))
+        .collect();
+    if matching.is_empty() {
+        None
+    } else {
+        Some((start, matching))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn channels() -> Channels {
+        let mut channels = Channels::new(10);
+        channels.join("forsen");
+        channels.join("xqc");
+        channels.part("forsen");
+        if let Some(channel) = channels.active_mut() {
+            channel.saw_chatter("bob");
+            channel.saw_chatter("alice");
+            channel.saw_chatter("albert");
+        }
+        channels
+    }
+
+    #[test]
+    fn completes_commands() {
+        let (start, candidates) = completions(":pa", &channels()).unwrap();
+        assert_eq!(start, 0);
+        assert_eq!(candidates, vec![":part"]);
+        assert_eq!(completions("hi :pa", &channels()), None);
+    }
+
+    #[test]
+    fn completes_channels() {
+        assert_eq!(
+            completions(":join ", &channels()),
+            Some((6, vec!["xqc".to_string(), "forsen".to_string()]))
+        );
+        assert_eq!(
+            completions(":j f", &channels()),
+            Some((3, vec!["forsen".to_string()]))
+        );
+        assert_eq!(completions(":part f", &channels()), None);
+        assert_eq!(completions(":join xqc f", &channels()), None);
+    }
+
+    #[test]
+    fn completes_mentions_most_recent_first() {
+        assert_eq!(
+            completions("hey @Al", &channels()),
+            Some((4, vec!["@albert".to_string(), "@alice".to_string()]))
+        );
+    }
+}
@@ -0,0 +1,286 @@
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chacha20poly1305::{
+    aead::{rand_core::RngCore, Aead, AeadCore, KeyInit, OsRng},
+    XChaCha20Poly1305, XNonce,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    io::{self, Write},
+    os::unix::fs::{OpenOptionsExt, PermissionsExt},
+    path::{Path, PathBuf},
+};
+use termion::input::TermRead;
+
+/// The OAuth token is kept out of the config file, in this file next to it.
+const SECRET_FILE: &str = "credentials.toml";
+
+/// Tries at the passphrase on start before reading chat anonymously.
+const PASSPHRASE_ATTEMPTS: usize = 3;
+
+/// Where the OAuth token for the config file at `config_path` is saved.
+pub fn secret_path(config_path: &Path) -> PathBuf {
+    config_path.with_file_name(SECRET_FILE)
+}
+
+/// The secret file holds either the token as is or the encrypted token.
+#[derive(Default, Serialize, Deserialize)]
+struct SecretFile {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    oauth_token: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    encrypted: Option<Encrypted>,
+}
+
+/// An OAuth token locked with a key derived from the user's passphrase
+/// by argon2id, along with everything but the passphrase needed to unlock it.
+#[derive(Serialize, Deserialize)]
+pub struct Encrypted {
+    m_cost: u32,
+    t_cost: u32,
+    p_cost: u32,
+    salt: String,
+    nonce: String,
+    token: String,
+}
+
+pub enum Secret {
+    Plain(String),
+    Encrypted(Encrypted),
+}
+
+/// Read the secret file, `None` if there isn't one yet.
+pub fn load(path: &Path) -> io::Result<Option<Secret>> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(error) => return Err(error),
+    };
+    // Only the user gets to read it, whoever created the file.
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    let file: SecretFile = toml::from_str(&contents).map_err(invalid)?;
+    Ok(match file {
+        SecretFile {
+            encrypted: Some(encrypted),
+            ..
+        } => Some(Secret::Encrypted(encrypted)),
+        SecretFile {
+            oauth_token: Some(token),
+            ..
+        } if !token.is_empty() => Some(Secret::Plain(oauth_token(&token))),
+        _ => None,
+    })
+}
+
+/// Write `token` to the secret file, readable by the user only and
+/// encrypted when a passphrase is given.
+pub fn save(path: &Path, token: &str, passphrase: Option<&str>) -> io::Result<()> {
+    let file = match passphrase {
+        Some(passphrase) => SecretFile {
+            encrypted: Some(Encrypted::new(token, passphrase)?),
+            ..SecretFile::default()
+        },
+        None => SecretFile {
+            oauth_token: Some(token.to_string()),
+            ..SecretFile::default()
+        },
+    };
+    let contents = toml::to_string(&file).map_err(invalid)?;
+    let mut secret_file = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?;
+    // The mode is only used for new files, an existing one is tightened
+    // before the token goes in.
+    secret_file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    secret_file.write_all(contents.as_bytes())
+}
+
+/// The saved OAuth token, asking for the passphrase on the terminal when
+/// it's encrypted. `None` when there's no token or no right passphrase was given.
+pub fn unlock(path: &Path, username: &str) -> io::Result<Option<String>> {
+    let encrypted = match load(path)? {
+        None => return Ok(None),
+        Some(Secret::Plain(token)) => return Ok(Some(token)),
+        Some(Secret::Encrypted(encrypted)) => encrypted,
+    };
+    let stdin = io::stdin();
+    let mut stdout = io::stdout();
+    for _ in 0..PASSPHRASE_ATTEMPTS {
+        write!(
+            stdout,
+            "Passphrase for {}'s OAuth token (Enter to read chat anonymously): ",
+            username
+        )?;
+        stdout.flush()?;
+        let passphrase = stdin.lock().read_passwd(&mut stdout)?.unwrap_or_default();
+        writeln!(stdout)?;
+        if passphrase.is_empty() {
+            return Ok(None);
+        }
+        if let Some(token) = encrypted.decrypt(&passphrase) {
+            return Ok(Some(oauth_token(&token)));
+        }
+        writeln!(stdout, "Wrong passphrase.")?;
+    }
+    Ok(None)
+}
+
+impl Encrypted {
+    fn new(token: &str, passphrase: &str) -> io::Result<Encrypted> {
+        let params = Params::default();
+        let mut salt = [0; 16];
+        OsRng.fill_bytes(&mut salt);
+        let key = derive_key(passphrase, &salt, params.clone())?;
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let token = XChaCha20Poly1305::new(&key.into())
+            .encrypt(&nonce, token.as_bytes())
+            .map_err(|_| invalid("the OAuth token couldn't be encrypted"))?;
+        Ok(Encrypted {
+            m_cost: params.m_cost(),
+            t_cost: params.t_cost(),
+            p_cost: params.p_cost(),
+            salt: STANDARD.encode(salt),
+            nonce: STANDARD.encode(nonce),
+            token: STANDARD.encode(token),
+        })
+    }
+
+    /// The token, or `None` if the passphrase is wrong or the file was changed.
+    pub fn decrypt(&self, passphrase: &str) -> Option<String> {
+        let params = Params::new(self.m_cost, self.t_cost, self.p_cost, None).ok()?;
+        let salt = STANDARD.decode(&self.salt).ok()?;
+        let nonce = STANDARD.decode(&self.nonce).ok()?;
+        let token = STANDARD.decode(&self.token).ok()?;
+        if nonce.len() != 24 {
+            return None;
+        }
+        let key = derive_key(passphrase, &salt, params).ok()?;
+        let token = XChaCha20Poly1305::new(&key.into())
+            .decrypt(XNonce::from_slice(&nonce), token.as_ref())
+            .ok()?;
+        String::from_utf8(token).ok()
+    }
+}
+
+/// Tokens are often copied with the `oauth:` prefix, the client adds its own.
+pub fn oauth_token(token: &str) -> String {
+    let token = token.trim();
+    token.strip_prefix("oauth:").unwrap_or(token).to_string()
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], params: Params) -> io::Result<[u8; 32]> {
+    let mut key = [0; 32];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|error| invalid(error.to_string()))?;
+    Ok(key)
+}
+
+fn invalid<E>(error: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+/// The steps of logging in on the input line, which shows none of
+/// the token or passphrase on screen.
+pub enum Prompt {
+    /// On the first start, whether to log in or read chat anonymously.
+    FirstRun,
+    /// The username to log in as, going on to ask for its token.
+    Username,
+    /// The OAuth token for `username`, logged in with straight away
+    /// and saved to the secret file when `save` is set.
+    Token { username: String, save: bool },
+    /// A passphrase to encrypt the saved token with, or none.
+    Passphrase { username: String, token: String },
+    /// The passphrase again, so a typo doesn't lock the token away.
+    RepeatPassphrase {
+        username: String,
+        token: String,
+        passphrase: String,
+    },
+}
+
+impl Prompt {
+    /// Shown on the input line in place of the prompt.
+    pub fn label(&self) -> String {
+        match self {
+            Prompt::FirstRun => {
+                "Log in to chat? y to enter your login, Enter to read anonymously: ".to_string()
+            }
+            Prompt::Username => "Twitch username: ".to_string(),
+            Prompt::Token { username, .. } => format!("OAuth token for {}: ", username),
+            Prompt::Passphrase { .. } => "Passphrase to encrypt it (Enter for none): ".to_string(),
+            Prompt::RepeatPassphrase { .. } => "Repeat the passphrase: ".to_string(),
+        }
+    }
+
+    /// Whether what's typed is hidden on screen.
+    pub fn is_secret(&self) -> bool {
+        !matches!(self, Prompt::FirstRun | Prompt::Username)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("tuitch-{}-{}.toml", name, std::process::id()))
+    }
+
+    fn mode(path: &Path) -> u32 {
+        fs::metadata(path).unwrap().permissions().mode() & 0o777
+    }
+
+    #[test]
+    fn encrypted_token_round_trips() {
+        let encrypted = Encrypted::new("abc123", "hunter2").unwrap();
+        assert_ne!(encrypted.token, "abc123");
+        assert_eq!(encrypted.decrypt("hunter2"), Some("abc123".to_string()));
+    }
+
+    #[test]
+    fn wrong_passphrase_is_refused() {
+        let encrypted = Encrypted::new("abc123", "hunter2").unwrap();
+        assert_eq!(encrypted.decrypt("hunter3"), None);
+        assert_eq!(encrypted.decrypt(""), None);
+    }
+
+    #[test]
+    fn secret_file_is_only_readable_by_the_user() {
+        let path = temp_path("secret-mode");
+        fs::write(&path, "").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        save(&path, "abc123", None).unwrap();
+        let saved_mode = mode(&path);
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        let loaded = load(&path).unwrap();
+        let loaded_mode = mode(&path);
+        fs::remove_file(&path).ok();
+        assert_eq!(saved_mode, 0o600);
+        assert_eq!(loaded_mode, 0o600);
+        assert!(matches!(loaded, Some(Secret::Plain(token)) if token == "abc123"));
+    }
+
+    #[test]
+    fn loaded_tokens_lose_their_prefix() {
+        let path = temp_path("secret-prefix");
+        fs::write(&path, "oauth_token = \"oauth:abc123 \"\n").unwrap();
+        let token = unlock(&path, "someone").unwrap();
+        fs::remove_file(&path).ok();
+        assert_eq!(token, Some("abc123".to_string()));
+    }
+
+    #[test]
+    fn strips_the_oauth_prefix() {
+        assert_eq!(oauth_token("oauth:abc123"), "abc123");
+        assert_eq!(oauth_token(" abc123\n"), "abc123");
+    }
+}
@@ -0,0 +1,335 @@
+use crate::theme::{Style, Theme};
+use crate::user_config::ConfigError;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    env, fs, io,
+    iter::once,
+    ops::Range,
+    os::unix::ffi::OsStrExt,
+    path::PathBuf,
+};
+use twitch_irc::message::Emote;
+
+/// The file in the cache directory listing BTTV, FFZ and 7TV emotes.
+pub const EMOTE_SETS_FILE: &str = "emotes.toml";
+
+/// Columns an emote picture takes up, emotes are about square and
+/// terminal cells twice as high as they are wide.
+const EMOTE_COLUMNS: usize = 2;
+
+/// Where an emote comes from, its id is the one given to it there.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Provider {
+    Twitch,
+    Bttv,
+    Ffz,
+    #[serde(rename = "7tv")]
+    SevenTv,
+}
+
+impl Provider {
+    fn name(&self) -> &'static str {
+        match self {
+            Provider::Twitch => "twitch",
+            Provider::Bttv => "bttv",
+            Provider::Ffz => "ffz",
+            Provider::SevenTv => "7tv",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct EmoteInfo {
+    pub provider: Provider,
+    pub id: String,
+}
+
+/// Third-party emotes by the word that shows them, `[global]` ones work
+/// in every chat and `[channels.<name>]` ones only in that channel's.
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct EmoteSets {
+    global: HashMap<String, EmoteInfo>,
+    channels: HashMap<String, HashMap<String, EmoteInfo>>,
+}
+
+/// Finds the emotes in chat messages and draws them, as text in the
+/// theme's emote style or as pictures with the kitty graphics protocol.
+///
+/// Everything is read from the cache directory, which other tools fill:
+/// `emotes.toml` with the third-party emote sets and the pictures as
+/// `emotes/<provider>/<id>.png`.
+pub struct Emotes {
+    sets: EmoteSets,
+    cache_dir: PathBuf,
+    images: bool,
+    /// The number kitty knows each picture by, `None` for emotes without one.
+    image_ids: HashMap<PathBuf, Option<u8>>,
+    next_image_id: u8,
+    /// Pictures the render task still has to send to the terminal.
+    pending: Vec<String>,
+}
+
+impl Emotes {
+    pub fn new(cache_dir: PathBuf, images: bool) -> Emotes {
+        Emotes {
+            sets: EmoteSets::default(),
+            cache_dir,
+            images,
+            image_ids: HashMap::new(),
+            next_image_id: 1,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Read the third-party emote sets, there are none without the file.
+    pub fn load_sets(&mut self) -> Result<(), ConfigError> {
+        let path = self.cache_dir.join(EMOTE_SETS_FILE);
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(source) => return Err(ConfigError::Read { path, source }),
+        };
+        let mut sets: EmoteSets =
+            toml::from_str(&contents).map_err(|error| ConfigError::parse(&path, error))?;
+        sets.channels = sets
+            .channels
+            .into_iter()
+            .map(|(channel, set)| (channel.trim_start_matches('#').to_lowercase(), set))
+            .collect();
+        self.sets = sets;
+        Ok(())
+    }
+
+    /// `text` with its emotes drawn and everything else in `style`.
+    /// `twitch` are the emotes Twitch found in it.
+    pub fn render(
+        &mut self,
+        text: &str,
+        twitch: &[Emote],
+        channel: &str,
+        style: &Style,
+        theme: &Theme,
+    ) -> String {
+        let mut line = String::new();
+        let mut end = 0;
+        for (range, emote) in self.find(text, twitch, channel) {
+            if range.start < end {
+                continue;
+            }
+            if range.start > end {
+                line.push_str(&style.paint(&text[end..range.start]));
+            }
+            match self.image(&emote) {
+                Some(id) => line.push_str(&placeholder(id)),
+                None => line.push_str(&theme.emote.paint(&text[range.clone()])),
+            }
+            end = range.end;
+        }
+        if end < text.len() {
+            line.push_str(&style.paint(&text[end..]));
+        }
+        line
+    }
+
+    /// The pictures to send to the terminal before drawing the next frame.
+    pub fn take_images(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.pending)
+    }
+
+    /// Where each emote is in `text` as byte ranges, in order. Words that
+    /// are a third-party emote count unless Twitch found one there already.
+    fn find(&self, text: &str, twitch: &[Emote], channel: &str) -> Vec<(Range<usize>, EmoteInfo)> {
+        // Twitch counts characters, and sometimes past the end of the text.
+        let offsets: Vec<usize> = text
+            .char_indices()
+            .map(|(offset, _)| offset)
+            .chain(once(text.len()))
+            .collect();
+        let mut emotes: Vec<(Range<usize>, EmoteInfo)> = twitch
+            .iter()
+            .filter_map(|emote| {
+                let start = *offsets.get(emote.char_range.start)?;
+                let end = *offsets.get(emote.char_range.end)?;
+                let info = EmoteInfo {
+                    provider: Provider::Twitch,
+                    id: emote.id.clone(),
+                };
+                Some((start..end, info))
+            })
+            .collect();
+
+        let channel_set = self.sets.channels.get(channel);
+        let mut start = 0;
+        for word in text.split(' ') {
+            let range = start..start + word.len();
+            start = range.end + 1;
+            let info = channel_set
+                .and_then(|set| set.get(word))
+                .or_else(|| self.sets.global.get(word));
+            let taken = emotes
+                .iter()
+                .any(|(other, _)| other.start < range.end && range.start < other.end);
+            if let (Some(info), false) = (info, taken || word.is_empty()) {
+                emotes.push((range, info.clone()));
+            }
+        }
+        emotes.sort_by_key(|(range, _)| range.start);
+        emotes
+    }
+
+    /// The number the picture of `emote` is sent to the terminal as, when
+    /// pictures are on and it's in the cache.
+    fn image(&mut self, emote: &EmoteInfo) -> Option<u8> {
+        if !self.images {
+            return None;
+        }
+        let path = self
+            .cache_dir
+            .join("emotes")
+            .join(emote.provider.name())
+            .join(format!("{}.png", emote.id));
+        if let Some(id) = self.image_ids.get(&path) {
+            return *id;
+        }
+        // Kitty tells pictures apart by the 256 colour palette colour of
+        // their placeholders, once it's used up the rest are text.
+        let id = Some(self.next_image_id).filter(|_| path.is_file() && self.next_image_id > 0);
+        if let Some(id) = id {
+            self.next_image_id = self.next_image_id.wrapping_add(1);
+            self.pending.push(format!(
+                "\x1b_Ga=T,U=1,t=f,f=100,i={},c={},r=1,q=2;{}\x1b\\",
+                id,
+                EMOTE_COLUMNS,
+                STANDARD.encode(path.as_os_str().as_bytes())
+            ));
+        }
+        self.image_ids.insert(path, id);
+        id
+    }
+}
+
+/// Cells kitty draws picture `id` over, the diacritics number the row and
+/// the column of the picture each one shows.
+fn placeholder(id: u8) -> String {
+    const COLUMNS: [char; EMOTE_COLUMNS] = ['\u{0305}', '\u{030D}'];
+    let cells: String = COLUMNS
+        .iter()
+        .map(|column| format!("\u{10EEEE}\u{0305}{}", column))
+        .collect();
+    format!("\x1b[38;5;{}m{}\x1b[0m", id, cells)
+}
+
+/// `$XDG_CACHE_HOME/tuitch`, or `~/.cache/tuitch` when that isn't set.
+pub fn cache_dir() -> PathBuf {
+    // Relative paths in XDG_CACHE_HOME are invalid and ignored.
+    env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .or_else(|| {
+            env::var_os("HOME")
+                .filter(|home| !home.is_empty())
+                .map(|home| PathBuf::from(home).join(".cache"))
+        })
+        .unwrap_or_default()
+        .join("tuitch")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn twitch(id: &str, char_range: Range<usize>, code: &str) -> Emote {
+        Emote {
+            id: id.to_string(),
+            char_range,
+            code: code.to_string(),
+        }
+    }
+
+    fn bttv(id: &str) -> EmoteInfo {
+        EmoteInfo {
+            provider: Provider::Bttv,
+            id: id.to_string(),
+        }
+    }
+
+    fn emotes() -> Emotes {
+        let mut emotes = Emotes::new(PathBuf::new(), false);
+        emotes.sets.global.insert("Kappa".to_string(), bttv("fake-kappa"));
+        emotes.sets.global.insert("catJAM".to_string(), bttv("global-cat"));
+        let mut channel = HashMap::new();
+        channel.insert("catJAM".to_string(), bttv("channel-cat"));
+        emotes.sets.channels.insert("xqc".to_string(), channel);
+        emotes
+    }
+
+    /// What `find` found, as the text it covers and where it's from.
+    fn found(emotes: &Emotes, text: &str, twitch: &[Emote], channel: &str) -> Vec<(String, String)> {
+        emotes
+            .find(text, twitch, channel)
+            .into_iter()
+            .map(|(range, info)| (text[range].to_string(), info.id))
+            .collect()
+    }
+
+    #[test]
+    fn twitch_ranges_count_characters() {
+        // é takes two bytes and ✓ three, Twitch counts each as one.
+        let text = "héllo Kappa ✓ Kappa";
+        let ranges: Vec<Range<usize>> = emotes()
+            .find(text, &[twitch("25", 6..11, "Kappa"), twitch("25", 14..19, "Kappa")], "")
+            .into_iter()
+            .map(|(range, _)| range)
+            .collect();
+        assert_eq!(ranges, vec![7..12, 17..22]);
+    }
+
+    #[test]
+    fn ranges_past_the_end_are_dropped() {
+        let found = found(&emotes(), "hi ✓", &[twitch("1", 3..9, "nope")], "");
+        assert!(found.is_empty());
+    }
+
+    #[test]
+    fn twitch_emotes_win_over_third_party_words() {
+        let text = "Kappa catJAM Kappa";
+        assert_eq!(
+            found(&emotes(), text, &[twitch("25", 0..5, "Kappa")], "forsen"),
+            vec![
+                ("Kappa".to_string(), "25".to_string()),
+                ("catJAM".to_string(), "global-cat".to_string()),
+                ("Kappa".to_string(), "fake-kappa".to_string()),
+            ]
+        );
+        // A Twitch emote over part of a word keeps the word from being one.
+        assert_eq!(
+            found(&emotes(), "catJAM", &[twitch("9", 0..3, "cat")], ""),
+            vec![("cat".to_string(), "9".to_string())]
+        );
+    }
+
+    #[test]
+    fn channel_emotes_come_before_global_ones() {
+        assert_eq!(
+            found(&emotes(), "catJAM", &[], "xqc"),
+            vec![("catJAM".to_string(), "channel-cat".to_string())]
+        );
+    }
+
+    #[test]
+    fn renders_emotes_in_the_emote_style() {
+        let theme = Theme::default();
+        let text = emotes().render(
+            "héllo Kappa!",
+            &[twitch("25", 6..11, "Kappa")],
+            "",
+            &Style::default(),
+            &theme,
+        );
+        assert_eq!(text, format!("héllo {}!", theme.emote.paint("Kappa")));
+    }
+}
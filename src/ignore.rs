@@ -0,0 +1,129 @@
+use crate::user_config::{edit_config_file, ConfigError, IgnoreConfig, Pattern};
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+};
+use toml_edit::{Array, Item};
+use twitch_irc::message::ServerMessage;
+
+/// The chatters and messages kept out of chat. Users are added and removed
+/// with `:ignore` and `:unignore` and saved to the config file right away,
+/// the patterns only come from the config.
+pub struct Ignores {
+    users: BTreeSet<String>,
+    patterns: Vec<Pattern>,
+    /// Show a line counting the hidden messages in their place.
+    pub show_hidden: bool,
+    config_path: PathBuf,
+}
+
+impl Ignores {
+    pub fn new(config: &IgnoreConfig, config_path: &Path) -> Ignores {
+        Ignores {
+            users: config.users.iter().map(|user| login(user)).collect(),
+            patterns: config.patterns.clone(),
+            show_hidden: config.show_hidden,
+            config_path: config_path.to_path_buf(),
+        }
+    }
+
+    /// Whether `message` is kept out of chat, only chat messages and
+    /// whispers ever are.
+    pub fn is_ignored(&self, message: &ServerMessage) -> bool {
+        let (sender, text) = match message {
+            ServerMessage::Privmsg(prvmsg) => (&prvmsg.sender.login, &prvmsg.message_text),
+            ServerMessage::Whisper(whisper) => (&whisper.sender.login, &whisper.message_text),
+            _ => return false,
+        };
+        self.users.contains(sender) || self.patterns.iter().any(|pattern| pattern.0.is_match(text))
+    }
+
+    /// Start ignoring `user`, returns false if they already were.
+    pub fn ignore(&mut self, user: &str) -> bool {
+        self.users.insert(login(user))
+    }
+
+    /// Stop ignoring `user`, returns false if they weren't.
+    pub fn unignore(&mut self, user: &str) -> bool {
+        self.users.remove(&login(user))
+    }
+
+    pub fn users(&self) -> impl Iterator<Item = &String> {
+        self.users.iter()
+    }
+
+    pub fn patterns(&self) -> &[Pattern] {
+        &self.patterns
+    }
+
+    /// Write the ignored users to the config file's `[ignore]` table,
+    /// leaving the rest of the file as it is, comments and all. A config
+    /// that doesn't parse is left for the user to fix.
+    pub async fn save(&self) -> Result<(), ConfigError> {
+        edit_config_file(&self.config_path, |document| {
+            let ignore = document
+                .entry("ignore")
+                .or_insert(toml_edit::table())
+                .as_table_like_mut()
+                .ok_or_else(|| "`ignore` should be a table".to_string())?;
+            let mut users: Array = self.users.iter().map(String::as_str).collect();
+            // A comment after the old list stays after the new one.
+            if let Some(old) = ignore.get("users").and_then(Item::as_value) {
+                *users.decor_mut() = old.decor().clone();
+            }
+            ignore.insert("users", toml_edit::value(users));
+            Ok(())
+        })
+        .await
+    }
+}
+
+/// `@Name` as the `name` Twitch logs chatters in as.
+fn login(user: &str) -> String {
+    user.trim_start_matches('@').to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[tokio::test]
+    async fn save_only_changes_the_ignored_users() {
+        let path = std::env::temp_dir().join(format!("tuitch-ignore-{}.toml", std::process::id()));
+        let config = "\
+# my settings
+theme = \"mine\" # the best one
+
+[themes.mine]
+highlight = \"bold yellow\"
+
+[ignore]
+users = [\"spammer\"] # bots mostly
+show_hidden = true
+";
+        fs::write(&path, config).unwrap();
+        let mut ignores = Ignores::new(&IgnoreConfig::default(), &path);
+        ignores.ignore("spammer");
+        ignores.ignore("@Bot");
+        ignores.save().await.unwrap();
+        let saved = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).ok();
+        assert_eq!(
+            saved,
+            config.replace(r#"["spammer"]"#, r#"["bot", "spammer"]"#)
+        );
+    }
+
+    #[tokio::test]
+    async fn save_adds_the_ignore_table() {
+        let path = std::env::temp_dir().join(format!("tuitch-ignore-new-{}.toml", std::process::id()));
+        fs::write(&path, "theme = \"dark\"\n").unwrap();
+        let mut ignores = Ignores::new(&IgnoreConfig::default(), &path);
+        ignores.ignore("bob");
+        ignores.save().await.unwrap();
+        let saved = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).ok();
+        assert_eq!(saved, "theme = \"dark\"\n\n[ignore]\nusers = [\"bob\"]\n");
+    }
+}
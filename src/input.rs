@@ -1,18 +1,751 @@
+use crate::credentials::Prompt;
+use std::{
+    collections::VecDeque,
+    fs,
+    io::{self, Write},
+    os::unix::fs::{OpenOptionsExt, PermissionsExt},
+    path::{Path, PathBuf},
+};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Most recent kills kept for yanking back.
+const KILL_RING_SIZE: usize = 16;
+
+/// The number of sent lines remembered when the config doesn't say.
+pub const DEFAULT_INPUT_HISTORY_SIZE: usize = 100;
+
+/// Commands that can carry an OAuth token, these are never kept in the history.
+const SECRET_COMMANDS: &[&str] = &[":credentials", ":login"];
+
+/// Lines the user has sent, oldest first, optionally saved to a file
+/// (one line each) so they carry over to the next session. Sent lines are
+/// appended to the file, which is rewritten without the duplicates and
+/// the lines past `capacity` on the next start.
+pub struct InputHistory {
+    entries: VecDeque<String>,
+    capacity: usize,
+    file: Option<PathBuf>,
+}
+
+impl Default for InputHistory {
+    fn default() -> InputHistory {
+        InputHistory::new(DEFAULT_INPUT_HISTORY_SIZE, None)
+    }
+}
+
+impl InputHistory {
+    /// Start the history from the lines in `file`, when it exists.
+    pub fn new(capacity: usize, file: Option<PathBuf>) -> InputHistory {
+        let mut history = InputHistory {
+            entries: VecDeque::new(),
+            capacity,
+            file: None,
+        };
+        let contents = file.as_ref().and_then(|file| fs::read_to_string(file).ok());
+        history.file = file;
+        if let Some(contents) = contents {
+            for line in contents.lines() {
+                history.push(line);
+            }
+            if contents.lines().count() > history.entries.len() {
+                // A history that can't be saved is still kept for the session.
+                history.save().ok();
+            }
+        }
+        history
+    }
+
+    /// Remember `line` as the newest entry. An older copy of the same line
+    /// is dropped rather than kept twice, and so is the oldest entry once
+    /// the history is full. Returns false if the line isn't kept at all.
+    pub fn push(&mut self, line: &str) -> bool {
+        let secret = SECRET_COMMANDS
+            .iter()
+            .any(|command| line.split_whitespace().next() == Some(command));
+        if line.trim().is_empty() || secret || self.capacity == 0 {
+            return false;
+        }
+        self.entries.retain(|entry| entry != line);
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(line.to_string());
+        true
+    }
+
+    /// Add `line` to the end of the history file, when there is one.
+    pub fn append(&self, line: &str) -> io::Result<()> {
+        match &self.file {
+            Some(file) => writeln!(open_history_file(file, false)?, "{}", line),
+            None => Ok(()),
+        }
+    }
+
+    /// Write the whole history to its file, when it has one.
+    pub fn save(&self) -> io::Result<()> {
+        match &self.file {
+            Some(file) => {
+                let mut contents = String::new();
+                for entry in &self.entries {
+                    contents.push_str(entry);
+                    contents.push('\n');
+                }
+                open_history_file(file, true)?.write_all(contents.as_bytes())
+            }
+            None => Ok(()),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&str> {
+        self.entries.get(index).map(String::as_str)
+    }
+
+    /// The newest entry older than `before` that contains `query`.
+    pub fn search(&self, query: &str, before: usize) -> Option<usize> {
+        self.entries
+            .iter()
+            .take(before)
+            .rposition(|entry| entry.contains(query))
+    }
+}
+
+/// Candidates for the word before the cursor, cycled through with Tab.
+struct Completion {
+    start: usize,
+    candidates: Vec<String>,
+    index: usize,
+}
+
+/// An incremental reverse search through the history (Ctrl-r).
+struct Search {
+    query: String,
+    found: Option<usize>,
+    // The input from before the search, put back if it's cancelled.
+    original: String,
+}
+
+/// The user's unsent input and a line editor over it.
+///
+/// The cursor is a byte offset into the input that always sits on a
+/// grapheme boundary, so multi-byte and combined characters (emoji with
+/// modifiers, CJK) are moved over and deleted as a single character.
 #[derive(Default)]
 pub struct InputBuffer {
     input: String,
     position: usize,
+    kill_ring: Vec<String>,
+    // Where the last yank was inserted and which kill ring entry it was,
+    // only set right after a yank so Alt-y can swap it for an older kill.
+    last_yank: Option<(usize, usize)>,
+    history: InputHistory,
+    // The history entry shown while going through it with Up/Down, and
+    // the unsent input to come back to past the newest entry.
+    browsing: Option<usize>,
+    draft: String,
+    search: Option<Search>,
+    completion: Option<Completion>,
+    // A secret being asked for, along with the input it took the place of.
+    prompt: Option<(Prompt, String)>,
 }
 
 impl InputBuffer {
-    pub fn get_input() -> String {
-        self.input
+    pub fn new(history: InputHistory) -> InputBuffer {
+        InputBuffer {
+            history,
+            ..InputBuffer::default()
+        }
+    }
+
+    pub fn get_input(&self) -> &str {
+        &self.input
+    }
+
+    pub fn before_cursor(&self) -> &str {
+        &self.input[..self.position]
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.input.is_empty()
+    }
+
+    /// Insert a character at the cursor.
+    pub fn insert(&mut self, new_input: char) {
+        self.interrupt();
+        self.input.insert(self.position, new_input);
+        self.position += new_input.len_utf8();
+    }
+
+    /// Insert text at the cursor.
+    pub fn insert_str(&mut self, text: &str) {
+        self.interrupt();
+        self.input.insert_str(self.position, text);
+        self.position += text.len();
     }
-    pub fn get_position() -> usize {
-        self.position
+
+    /// Delete the character before the cursor.
+    pub fn backspace(&mut self) {
+        let start = self.prev_boundary(self.position);
+        self.remove(start, self.position);
+    }
+
+    /// Delete the character under the cursor.
+    pub fn delete(&mut self) {
+        let end = self.next_boundary(self.position);
+        self.remove(self.position, end);
+    }
+
+    pub fn move_left(&mut self) {
+        self.interrupt();
+        self.position = self.prev_boundary(self.position);
+    }
+
+    pub fn move_right(&mut self) {
+        self.interrupt();
+        self.position = self.next_boundary(self.position);
+    }
+
+    pub fn move_home(&mut self) {
+        self.interrupt();
+        self.position = 0;
+    }
+
+    pub fn move_end(&mut self) {
+        self.interrupt();
+        self.position = self.input.len();
+    }
+
+    /// Move back to the start of the current or previous word.
+    pub fn move_word_left(&mut self) {
+        self.interrupt();
+        self.position = self.word_start(self.position);
+    }
+
+    /// Move forward to the end of the current or next word.
+    pub fn move_word_right(&mut self) {
+        self.interrupt();
+        self.position = self.word_end(self.position);
+    }
+
+    /// Kill the whitespace separated word before the cursor (Ctrl-w).
+    pub fn kill_word_before(&mut self) {
+        let before = &self.input[..self.position];
+        let trimmed = before.trim_end();
+        let start = trimmed
+            .rfind(char::is_whitespace)
+            .map_or(0, |index| index + trimmed[index..].chars().next().unwrap().len_utf8());
+        self.kill(start, self.position);
+    }
+
+    /// Kill everything before the cursor (Ctrl-u).
+    pub fn kill_to_start(&mut self) {
+        self.kill(0, self.position);
+    }
+
+    /// Kill everything from the cursor on (Ctrl-k).
+    pub fn kill_to_end(&mut self) {
+        self.kill(self.position, self.input.len());
+    }
+
+    /// Insert the most recent kill at the cursor (Ctrl-y).
+    pub fn yank(&mut self) {
+        if let Some(text) = self.kill_ring.last().cloned() {
+            let start = self.position;
+            self.insert_str(&text);
+            self.last_yank = Some((start, self.kill_ring.len() - 1));
+        }
+    }
+
+    /// Replace the text just yanked with the kill before it (Alt-y).
+    pub fn yank_pop(&mut self) {
+        if let Some((start, index)) = self.last_yank {
+            let index = if index == 0 {
+                self.kill_ring.len() - 1
+            } else {
+                index - 1
+            };
+            self.input.replace_range(start..self.position, "");
+            self.position = start;
+            let text = self.kill_ring[index].clone();
+            self.insert_str(&text);
+            self.last_yank = Some((start, index));
+        }
+    }
+
+    /// Empty the buffer and return what was in it, the kill ring is kept.
+    pub fn take(&mut self) -> String {
+        self.position = 0;
+        self.interrupt();
+        self.browsing = None;
+        self.draft.clear();
+        std::mem::take(&mut self.input)
+    }
+
+    /// Empty the buffer and add what was in it to the history.
+    pub fn submit(&mut self) -> String {
+        let line = self.take();
+        if self.history.push(&line) {
+            // A history that can't be saved is still kept for the session.
+            self.history.append(&line).ok();
+        }
+        line
+    }
+
+    /// Ask for a secret on the input line, what was typed before comes
+    /// back once it's answered or cancelled.
+    pub fn ask(&mut self, prompt: Prompt) {
+        let stashed = match self.prompt.take() {
+            Some((_, stashed)) => stashed,
+            None => self.take(),
+        };
+        self.prompt = Some((prompt, stashed));
+    }
+
+    pub fn prompt(&self) -> Option<&Prompt> {
+        self.prompt.as_ref().map(|(prompt, _)| prompt)
+    }
+
+    /// The prompt and what was typed at it, which never goes in the history.
+    pub fn answer(&mut self) -> Option<(Prompt, String)> {
+        let (prompt, stashed) = self.prompt.take()?;
+        let answer = self.take();
+        self.set_input(stashed);
+        Some((prompt, answer))
+    }
+
+    /// Drop the secret typed so far, true if one was being asked for.
+    pub fn cancel_prompt(&mut self) -> bool {
+        self.answer().is_some()
     }
-    pub fn push_input(new_input: char) {
-        self.input.push(new_input);
-        self.position += 1;
+
+    /// Show the entry before the one shown, starting from the newest (Up).
+    pub fn history_prev(&mut self) {
+        let index = match self.browsing {
+            Some(0) => return,
+            Some(index) => index - 1,
+            None if self.history.len() == 0 => return,
+            None => {
+                self.draft = self.input.clone();
+                self.history.len() - 1
+            }
+        };
+        self.browsing = Some(index);
+        let entry = self.history.get(index).unwrap_or_default().to_string();
+        self.set_input(entry);
+    }
+
+    /// Show the entry after the one shown, going past the newest entry
+    /// brings back what was typed before browsing (Down).
+    pub fn history_next(&mut self) {
+        if let Some(index) = self.browsing {
+            if index + 1 < self.history.len() {
+                self.browsing = Some(index + 1);
+                let entry = self.history.get(index + 1).unwrap_or_default().to_string();
+                self.set_input(entry);
+            } else {
+                self.browsing = None;
+                let draft = std::mem::take(&mut self.draft);
+                self.set_input(draft);
+            }
+        }
+    }
+
+    pub fn is_searching(&self) -> bool {
+        self.search.is_some()
+    }
+
+    /// Start a reverse search, or look for an older match if one is going.
+    pub fn search_older(&mut self) {
+        match &mut self.search {
+            Some(search) => {
+                let before = search.found.unwrap_or(self.history.len());
+                if let Some(found) = self.history.search(&search.query, before) {
+                    search.found = Some(found);
+                }
+            }
+            None => {
+                self.search = Some(Search {
+                    query: String::new(),
+                    found: None,
+                    original: self.input.clone(),
+                })
+            }
+        }
+    }
+
+    pub fn search_insert(&mut self, c: char) {
+        if let Some(search) = &mut self.search {
+            search.query.push(c);
+            let before = search.found.map_or(self.history.len(), |found| found + 1);
+            search.found = self.history.search(&search.query, before);
+        }
+    }
+
+    pub fn search_backspace(&mut self) {
+        if let Some(search) = &mut self.search {
+            search.query.pop();
+            search.found = self.history.search(&search.query, self.history.len());
+        }
+    }
+
+    /// End the search, leaving the match in the buffer to edit or send.
+    pub fn accept_search(&mut self) {
+        if let Some(search) = self.search.take() {
+            let line = match search.found.and_then(|found| self.history.get(found)) {
+                Some(found) => found.to_string(),
+                None => search.original,
+            };
+            self.set_input(line);
+        }
+    }
+
+    /// End the search, putting back the input from before it.
+    pub fn cancel_search(&mut self) {
+        if let Some(search) = self.search.take() {
+            self.set_input(search.original);
+        }
+    }
+
+    /// The search query and the entry it matches, while searching.
+    pub fn search_view(&self) -> Option<(&str, Option<&str>)> {
+        self.search.as_ref().map(|search| {
+            let found = search.found.and_then(|found| self.history.get(found));
+            (search.query.as_str(), found)
+        })
+    }
+
+    pub fn is_completing(&self) -> bool {
+        self.completion.is_some()
+    }
+
+    /// Replace the word from `start` to the cursor with the first of
+    /// `candidates`, Tab then cycles through the rest.
+    pub fn complete(&mut self, start: usize, candidates: Vec<String>) {
+        if candidates.is_empty() {
+            return;
+        }
+        self.completion = Some(Completion {
+            start,
+            candidates,
+            index: 0,
+        });
+        self.apply_completion();
+    }
+
+    /// Swap the completed word for the next candidate, or the previous one.
+    pub fn cycle_completion(&mut self, forward: bool) {
+        if let Some(completion) = &mut self.completion {
+            let count = completion.candidates.len();
+            completion.index = if forward {
+                (completion.index + 1) % count
+            } else {
+                (completion.index + count - 1) % count
+            };
+            self.apply_completion();
+        }
+    }
+
+    fn apply_completion(&mut self) {
+        if let Some(completion) = &self.completion {
+            let candidate = format!("{} ", completion.candidates[completion.index]);
+            self.input
+                .replace_range(completion.start..self.position, &candidate);
+            self.position = completion.start + candidate.len();
+        }
+    }
+
+    /// Any edit that isn't a yank or completion ends a yank-pop or Tab cycle.
+    fn interrupt(&mut self) {
+        self.last_yank = None;
+        self.completion = None;
+    }
+
+    /// Replace the input, with the cursor at the end.
+    pub fn set_input(&mut self, input: String) {
+        self.input = input;
+        self.position = self.input.len();
+        self.interrupt();
+    }
+
+    fn remove(&mut self, start: usize, end: usize) {
+        self.interrupt();
+        self.input.replace_range(start..end, "");
+        self.position = start;
+    }
+
+    fn kill(&mut self, start: usize, end: usize) {
+        if start == end {
+            return;
+        }
+        if self.kill_ring.len() == KILL_RING_SIZE {
+            self.kill_ring.remove(0);
+        }
+        self.kill_ring.push(self.input[start..end].to_string());
+        self.remove(start, end);
+    }
+
+    fn prev_boundary(&self, position: usize) -> usize {
+        self.input[..position]
+            .grapheme_indices(true)
+            .next_back()
+            .map_or(0, |(index, _)| index)
+    }
+
+    fn next_boundary(&self, position: usize) -> usize {
+        self.input[position..]
+            .graphemes(true)
+            .next()
+            .map_or(position, |grapheme| position + grapheme.len())
+    }
+
+    fn word_start(&self, position: usize) -> usize {
+        let mut start = position;
+        let mut in_word = false;
+        for (index, grapheme) in self.input[..position].grapheme_indices(true).rev() {
+            let word = is_word(grapheme);
+            if in_word && !word {
+                break;
+            }
+            in_word |= word;
+            start = index;
+        }
+        start
+    }
+
+    fn word_end(&self, position: usize) -> usize {
+        let mut end = position;
+        let mut in_word = false;
+        for (index, grapheme) in self.input[position..].grapheme_indices(true) {
+            let word = is_word(grapheme);
+            if in_word && !word {
+                break;
+            }
+            in_word |= word;
+            end = position + index + grapheme.len();
+        }
+        end
+    }
+}
+
+fn is_word(grapheme: &str) -> bool {
+    grapheme.chars().any(char::is_alphanumeric)
+}
+
+/// Open the history file to add to it or, with `truncate`, to rewrite it.
+/// Only the user can read it, what's typed can be private.
+fn open_history_file(path: &Path, truncate: bool) -> io::Result<fs::File> {
+    let file = fs::OpenOptions::new()
+        .append(!truncate)
+        .write(truncate)
+        .truncate(truncate)
+        .create(true)
+        .mode(0o600)
+        .open(path)?;
+    // The mode is only used for new files, an existing one is tightened
+    // before anything goes in.
+    file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    Ok(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer(text: &str) -> InputBuffer {
+        let mut buffer = InputBuffer::default();
+        buffer.insert_str(text);
+        buffer
+    }
+
+    #[test]
+    fn inserts_at_cursor() {
+        let mut input = buffer("helo");
+        input.move_left();
+        input.insert('l');
+        assert_eq!(input.get_input(), "hello");
+        assert_eq!(input.before_cursor(), "hell");
+    }
+
+    #[test]
+    fn backspace_and_delete_at_cursor() {
+        let mut input = buffer("hello");
+        input.move_home();
+        input.backspace();
+        assert_eq!(input.get_input(), "hello");
+        input.delete();
+        assert_eq!(input.get_input(), "ello");
+        input.move_end();
+        input.delete();
+        input.backspace();
+        assert_eq!(input.get_input(), "ell");
+    }
+
+    #[test]
+    fn moves_over_multi_byte_characters() {
+        let mut input = buffer("aé日👍🏽");
+        input.move_left();
+        assert_eq!(input.before_cursor(), "aé日");
+        input.move_left();
+        input.move_left();
+        assert_eq!(input.before_cursor(), "a");
+        input.move_right();
+        input.delete();
+        assert_eq!(input.get_input(), "aé👍🏽");
+        input.move_end();
+        input.backspace();
+        assert_eq!(input.get_input(), "aé");
+    }
+
+    #[test]
+    fn word_motions() {
+        let mut input = buffer("join  some_channel now");
+        input.move_word_left();
+        assert_eq!(input.before_cursor(), "join  some_channel ");
+        input.move_word_left();
+        assert_eq!(input.before_cursor(), "join  some_");
+        input.move_word_left();
+        input.move_word_left();
+        assert_eq!(input.before_cursor(), "");
+        input.move_word_right();
+        assert_eq!(input.before_cursor(), "join");
+        input.move_word_right();
+        assert_eq!(input.before_cursor(), "join  some");
+    }
+
+    #[test]
+    fn kill_word_before_cursor() {
+        let mut input = buffer(":join  日本 ");
+        input.kill_word_before();
+        assert_eq!(input.get_input(), ":join  ");
+        input.kill_word_before();
+        assert_eq!(input.get_input(), "");
+    }
+
+    #[test]
+    fn kill_line_and_yank() {
+        let mut input = buffer("hello world");
+        input.move_word_left();
+        input.kill_to_end();
+        assert_eq!(input.get_input(), "hello ");
+        input.kill_to_start();
+        assert_eq!(input.get_input(), "");
+        input.yank();
+        assert_eq!(input.get_input(), "hello ");
+        input.yank_pop();
+        assert_eq!(input.get_input(), "world");
+        input.yank_pop();
+        assert_eq!(input.get_input(), "hello ");
+        input.move_home();
+        input.yank_pop();
+        assert_eq!(input.get_input(), "hello ");
+    }
+
+    #[test]
+    fn history_recall_keeps_draft() {
+        let mut input = InputBuffer::default();
+        input.insert_str(":join a");
+        input.submit();
+        input.insert_str("hello");
+        input.submit();
+        input.insert_str("draft");
+        input.history_prev();
+        assert_eq!(input.get_input(), "hello");
+        input.history_prev();
+        input.history_prev();
+        assert_eq!(input.get_input(), ":join a");
+        input.history_next();
+        input.history_next();
+        assert_eq!(input.get_input(), "draft");
+        input.history_next();
+        assert_eq!(input.get_input(), "draft");
+    }
+
+    #[test]
+    fn history_suppresses_duplicates_and_caps() {
+        let mut history = InputHistory::new(2, None);
+        history.push("one");
+        history.push("two");
+        history.push("one");
+        assert_eq!(history.len(), 2);
+        assert_eq!(history.get(1), Some("one"));
+        history.push("three");
+        assert_eq!(history.get(0), Some("one"));
+        history.push(":credentials user token");
+        history.push("  ");
+        assert_eq!(history.get(1), Some("three"));
+    }
+
+    #[test]
+    fn history_file_is_appended_and_compacted() {
+        let path = std::env::temp_dir().join(format!("tuitch-history-{}", std::process::id()));
+        fs::remove_file(&path).ok();
+        let mut input = InputBuffer::new(InputHistory::new(2, Some(path.clone())));
+        for line in ["a", "b", "a", ":login me token", "c"].iter() {
+            input.insert_str(line);
+            input.submit();
+        }
+        let appended = fs::read_to_string(&path).unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode() & 0o777;
+        let history = InputHistory::new(2, Some(path.clone()));
+        let compacted = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).ok();
+        assert_eq!(appended, "a\nb\na\nc\n");
+        assert_eq!(mode, 0o600);
+        assert_eq!((history.get(0), history.get(1)), (Some("a"), Some("c")));
+        assert_eq!(compacted, "a\nc\n");
+    }
+
+    #[test]
+    fn reverse_search() {
+        let mut input = InputBuffer::default();
+        for line in [":join first", "hi", ":join second"].iter() {
+            input.insert_str(line);
+            input.submit();
+        }
+        input.insert_str("typed");
+        input.search_older();
+        input.search_insert('j');
+        assert_eq!(input.search_view(), Some(("j", Some(":join second"))));
+        input.search_older();
+        assert_eq!(input.search_view(), Some(("j", Some(":join first"))));
+        input.search_older();
+        assert_eq!(input.search_view(), Some(("j", Some(":join first"))));
+        input.accept_search();
+        assert_eq!(input.get_input(), ":join first");
+
+        input.search_older();
+        input.search_insert('x');
+        assert_eq!(input.search_view(), Some(("x", None)));
+        input.cancel_search();
+        assert_eq!(input.get_input(), ":join first");
+    }
+
+    #[test]
+    fn completion_cycles_candidates() {
+        let mut input = buffer("hi @fo");
+        input.complete(3, vec!["@foo".to_string(), "@forsen".to_string()]);
+        assert_eq!(input.get_input(), "hi @foo ");
+        input.cycle_completion(true);
+        assert_eq!(input.get_input(), "hi @forsen ");
+        input.cycle_completion(true);
+        assert_eq!(input.get_input(), "hi @foo ");
+        input.cycle_completion(false);
+        assert_eq!(input.get_input(), "hi @forsen ");
+        input.insert('!');
+        assert!(!input.is_completing());
+    }
+
+    #[test]
+    fn take_keeps_kill_ring() {
+        let mut input = buffer("first");
+        input.kill_to_start();
+        input.insert_str("second");
+        assert_eq!(input.take(), "second");
+        assert_eq!(input.before_cursor(), "");
+        input.yank();
+        assert_eq!(input.get_input(), "first");
     }
 }
@@ -0,0 +1,151 @@
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use termion::event::Key;
+
+/// A key as it's written in the config, e.g. `ctrl-q`, `alt-n`, `pageup` or `f5`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct KeyBinding(pub Key);
+
+impl KeyBinding {
+    pub fn parse(name: &str) -> Option<KeyBinding> {
+        let lower = name.to_lowercase();
+        let key = if let Some(c) = lower.strip_prefix("ctrl-").and_then(single_char) {
+            Key::Ctrl(c)
+        } else if let Some(c) = name
+            .get(4..)
+            .filter(|_| lower.starts_with("alt-"))
+            .and_then(single_char)
+        {
+            Key::Alt(c)
+        } else if let Some(number) = lower.strip_prefix('f').and_then(|n| n.parse().ok()) {
+            Key::F(number)
+        } else {
+            match lower.as_str() {
+                "up" => Key::Up,
+                "down" => Key::Down,
+                "left" => Key::Left,
+                "right" => Key::Right,
+                "home" => Key::Home,
+                "end" => Key::End,
+                "pageup" => Key::PageUp,
+                "pagedown" => Key::PageDown,
+                "insert" => Key::Insert,
+                "delete" => Key::Delete,
+                "backspace" => Key::Backspace,
+                "backtab" => Key::BackTab,
+                "esc" => Key::Esc,
+                _ => Key::Char(single_char(name)?),
+            }
+        };
+        Some(KeyBinding(key))
+    }
+
+    pub fn name(&self) -> String {
+        match self.0 {
+            Key::Ctrl(c) => format!("ctrl-{}", c),
+            Key::Alt(c) => format!("alt-{}", c),
+            Key::F(number) => format!("f{}", number),
+            Key::Up => "up".to_string(),
+            Key::Down => "down".to_string(),
+            Key::Left => "left".to_string(),
+            Key::Right => "right".to_string(),
+            Key::Home => "home".to_string(),
+            Key::End => "end".to_string(),
+            Key::PageUp => "pageup".to_string(),
+            Key::PageDown => "pagedown".to_string(),
+            Key::Insert => "insert".to_string(),
+            Key::Delete => "delete".to_string(),
+            Key::Backspace => "backspace".to_string(),
+            Key::BackTab => "backtab".to_string(),
+            Key::Esc => "esc".to_string(),
+            Key::Char(c) => c.to_string(),
+            _ => "unknown".to_string(),
+        }
+    }
+}
+
+fn single_char(text: &str) -> Option<char> {
+    let mut chars = text.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(c),
+        _ => None,
+    }
+}
+
+impl Serialize for KeyBinding {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.name())
+    }
+}
+
+impl<'de> Deserialize<'de> for KeyBinding {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<KeyBinding, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        KeyBinding::parse(&name).ok_or_else(|| {
+            de::Error::custom(format!(
+                "unknown key `{}`, expected e.g. `ctrl-q`, `alt-n`, `pageup` or `f5`",
+                name
+            ))
+        })
+    }
+}
+
+/// The keys for actions that aren't line editing, set in the `[keys]` table.
+/// A bound key is taken before any built-in use it has.
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeyBindings {
+    pub quit: KeyBinding,
+    pub next_tab: KeyBinding,
+    pub prev_tab: KeyBinding,
+    pub scroll_up: KeyBinding,
+    pub scroll_down: KeyBinding,
+    pub search_history: KeyBinding,
+    /// Pick a message in the chat to reply to.
+    pub reply: KeyBinding,
+    /// Show the highlighted messages from every channel, or go back.
+    pub mentions: KeyBinding,
+    /// Show or hide who's in the chat on screen.
+    pub users: KeyBinding,
+    /// Open the conversation with unread whispers, or go back.
+    pub whispers: KeyBinding,
+}
+
+impl Default for KeyBindings {
+    fn default() -> KeyBindings {
+        KeyBindings {
+            quit: KeyBinding(Key::Ctrl('q')),
+            next_tab: KeyBinding(Key::Ctrl('n')),
+            prev_tab: KeyBinding(Key::Ctrl('p')),
+            scroll_up: KeyBinding(Key::PageUp),
+            scroll_down: KeyBinding(Key::PageDown),
+            search_history: KeyBinding(Key::Ctrl('r')),
+            reply: KeyBinding(Key::Alt('r')),
+            mentions: KeyBinding(Key::Alt('m')),
+            users: KeyBinding(Key::Alt('u')),
+            whispers: KeyBinding(Key::Alt('w')),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_round_trip() {
+        for name in ["ctrl-a", "alt-x", "alt-X", "pageup", "backtab", "f5", "f12", "q", "f"] {
+            let binding = KeyBinding::parse(name).unwrap();
+            assert_eq!(binding.name(), name);
+        }
+        assert_eq!(KeyBinding::parse("Ctrl-A"), Some(KeyBinding(Key::Ctrl('a'))));
+        assert_eq!(KeyBinding::parse("PageUp"), Some(KeyBinding(Key::PageUp)));
+        assert_eq!(KeyBinding::parse("f5"), Some(KeyBinding(Key::F(5))));
+    }
+
+    #[test]
+    fn rejects_unknown_names() {
+        for name in ["", "pgup", "ctrl-ab", "alt-", "hyper-a", "fx", "enter key"] {
+            assert_eq!(KeyBinding::parse(name), None, "{}", name);
+        }
+    }
+}
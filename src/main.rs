@@ -1,81 +1,346 @@
-#![allow()]
-
-use crate::commands::run_command;
-use crate::messages::{format_message, print_message, send_user_message};
-use crate::user_config::{get_client_config, set_client_config};
-use crate::user_interface::home_screen;
-use std::{io::stdout, io::Write, sync::{Arc, mpsc::{self, TryRecvError}}};
-use termion::{input::TermRead, raw::IntoRawMode, screen::AlternateScreen};
-use tokio::{select, sync::broadcast, sync::RwLock, task};
-use twitch_irc::{login::StaticLoginCredentials, SecureTCPTransport, TwitchIRCClient};
+use crate::channels::{Channels, ChatterInfo};
+use crate::commands::{answer_prompt, current, request_membership, run_command, whispers_command};
+use crate::completion::completions;
+use crate::input::{InputBuffer, InputHistory};
+use crate::messages::{
+    format_message, format_whisper, hide_message, is_highlight, message_channel, send_user_message,
+    send_whisper, update_channel, update_mentions,
+};
+use crate::credentials::Prompt;
+use crate::emotes::{cache_dir, Emotes};
+use crate::ignore::Ignores;
+use crate::notify::{notifiers, Notification};
+use crate::theme::Themes;
+use crate::user_config::{
+    config_path, get_client_config, import_legacy_config, set_client_config, ConfigError,
+    UserConfig,
+};
+use owo_colors::OwoColorize;
+use crate::user_interface::{Connection, Screen, Status};
+use std::{io::stdout, io::Write, path::PathBuf, sync::Arc};
+use termion::{input::TermRead, screen::AlternateScreen};
+use tokio::{
+    select,
+    signal::unix::{signal, SignalKind},
+    sync::broadcast,
+    sync::mpsc,
+    sync::RwLock,
+};
+use twitch_irc::{
+    login::StaticLoginCredentials, message::ServerMessage, ClientConfig, SecureTCPTransport,
+    TwitchIRCClient,
+};
 
+mod badges;
+mod channels;
+mod cli;
 mod commands;
+mod completion;
+mod credentials;
+mod emotes;
+mod ignore;
+mod input;
+mod keys;
 mod messages;
+mod moderation;
+mod notify;
+mod scrollback;
+mod theme;
 mod user_config;
 mod user_interface;
 
+/// The Twitch client shared by every task.
+pub type Client = TwitchIRCClient<SecureTCPTransport, StaticLoginCredentials>;
+
+/// The messages received by a `Client`.
+pub type IncomingMessages = mpsc::UnboundedReceiver<ServerMessage>;
+
 #[tokio::main]
 pub async fn main() -> std::io::Result<()> {
     // TODO: Add another tokio task for ctrl-c handling.
     // TODO: Need error-handling for channels
     // that do not exist and incorrect user input.
 
+    let args = cli::parse_args().unwrap_or_else(|error| cli::usage_error(error));
+
     // User config path and the config struct itself,
     // the struct is built from the contents of the config file
     // and used to access the current username data.
-    // this is probably a temp setup for config while in development
-    // and will likely change when a more streamlined login
-    // and config system is done, including a working :login
-    // command for the user.
-    let config_path = "Config.toml";
-    let user_config = get_client_config(config_path).await;
-
-    let current_channel = Arc::new(RwLock::new(String::new()));
-    let user_name = Arc::new(RwLock::new(user_config.username));
-    let current_channel_read = Arc::clone(&current_channel);
-    let _user_name_read = Arc::clone(&user_name);
+    let config_path = config_path(args.config);
+    // A config an older version left in the current directory is only
+    // picked up once, it's fine to start without it.
+    import_legacy_config(&config_path).ok();
+
+    // Without a config file this is the first start, which is set up once
+    // the screen is. A config that can't be read is left alone and the
+    // defaults are used, what's wrong with it is shown on the home screen.
+    let mut config_errors = Vec::new();
+    let mut first_run = false;
+    let user_config = match get_client_config(&config_path).await {
+        Ok(config) => config,
+        Err(ConfigError::Missing(_)) => {
+            first_run = true;
+            UserConfig::default()
+        }
+        Err(error) => {
+            config_errors.push(error);
+            UserConfig::default()
+        }
+    };
+
+    // The TwitchIRCClient is built with either the default (read-only) or Twitch
+    // login credentials (username & OAuth token pair), the token is read from
+    // the secret file and its passphrase asked for before the screen is set up.
+    let client_config = set_client_config(&config_path, &user_config, args.anonymous)
+        .await
+        .unwrap_or_else(|error| {
+            config_errors.push(error);
+            ClientConfig::default()
+        });
+
+    // Every joined channel gets its own tab and message buffer,
+    // shared between the incoming, input and command tasks.
+    let channels = Arc::new(RwLock::new(Channels::new(user_config.history_size)));
+    let channels_incoming = Arc::clone(&channels);
+    let channels_command = Arc::clone(&channels);
+    let channels_render = Arc::clone(&channels);
+
+    // Login and connection state for the status bar.
+    let login = match &client_config.login_credentials.credentials.token {
+        Some(_) => client_config.login_credentials.credentials.login.clone(),
+        None => String::new(),
+    };
+    let (mut incoming_messages, client) = Client::new(client_config);
+
+    let status = Arc::new(RwLock::new(Status::new(login)));
+    let status_incoming = Arc::clone(&status);
+    let status_command = Arc::clone(&status);
+    let status_render = Arc::clone(&status);
+
+    // Channels from the config's autojoin list and given with --channel
+    // are open from the start.
+    let autojoin: Vec<&String> = user_config.autojoin.iter().chain(&args.channels).collect();
+    if !autojoin.is_empty() {
+        let mut channels = channels.write().await;
+        for channel in autojoin {
+            channels.join(channel);
+        }
+        request_membership(&client);
+        client.set_wanted_channels(channels.names());
+    }
+
+    // Emotes from other sites are only known from the cache file, they're
+    // formatted by the first task and their pictures sent by the render task.
+    let mut emotes = Emotes::new(cache_dir(), user_config.emotes.images);
+    if let Err(error) = emotes.load_sets() {
+        config_errors.push(error);
+    }
+    let emotes = Arc::new(RwLock::new(emotes));
+    let emotes_render = Arc::clone(&emotes);
+
+    // Whatever is wrong with the config shows in the tab on screen, as
+    // does anything migrating it changed.
+    for error in config_errors {
+        channels.write().await.notify(error.to_string().red().to_string());
+    }
+    for notice in &user_config.notices {
+        channels.write().await.notify(notice.clone());
+    }
+
+    // The theme is shared by every task that draws or formats, and
+    // switched with :theme.
+    let mut themes = Themes::new(user_config.themes.clone(), &config_path);
+    if let Err(error) = themes.switch(&user_config.theme) {
+        channels.write().await.notify(
+            format!("{} Using the {} theme.", error, themes.name())
+                .red()
+                .to_string(),
+        );
+    }
+    let themes = Arc::new(RwLock::new(themes));
+    let themes_incoming = Arc::clone(&themes);
+    let themes_command = Arc::clone(&themes);
+    let themes_render = Arc::clone(&themes);
+
+    // Ignored chatters and patterns, checked before messages are formatted
+    // and changed with :ignore and :unignore.
+    let ignores = Arc::new(RwLock::new(Ignores::new(&user_config.ignore, &config_path)));
+    let ignores_command = Arc::clone(&ignores);
 
     // Input-buffer for user's typed input and chat messages.
-    // This is a shared state to allow proper handling with incoming
-    // server messages while unsent user input is in the console.
-    let input_buffer_lock = Arc::new(RwLock::new(String::new()));
-    let input_buffer = Arc::clone(&input_buffer_lock);
-    let input_buffer2 = Arc::clone(&input_buffer_lock);
+    // This is a shared state so the input line can be redrawn
+    // along with incoming server messages.
+    let input_history = InputHistory::new(
+        user_config.input_history_size,
+        user_config.input_history_file.as_ref().map(PathBuf::from),
+    );
+    let input_buffer = Arc::new(RwLock::new(InputBuffer::new(input_history)));
+    let input_buffer_command = Arc::clone(&input_buffer);
+    let input_buffer_render = Arc::clone(&input_buffer);
+
+    // The settings are read-only from here on, shared by the tasks that
+    // format messages and handle keys.
+    let config = Arc::new(user_config);
+    let config_incoming = Arc::clone(&config);
+    let config_command = Arc::clone(&config);
+
+    // Highlighted messages are also handed to these, for desktop
+    // notifications or a command of the user's.
+    let notifiers = notifiers(&config.notifications);
+
+    // The first start asks whether to log in, on the input line, and writes
+    // the config file once that's answered. --anonymous already answers it
+    // for this session only.
+    if first_run && !args.anonymous {
+        channels.write().await.notify(format!(
+            "Welcome to tuitch! There is no config file at {} yet.",
+            config_path.display()
+        ));
+        input_buffer.write().await.ask(Prompt::FirstRun);
+    }
 
     // Create tx/rx to send and receive shutdown signal
     // when specific user input is detected.
-    let (shutdown_tx, mut shutdown_rx) = broadcast::channel(2);
+    let (shutdown_tx, mut shutdown_rx) = broadcast::channel(3);
     let mut shutdown_rx2 = shutdown_tx.subscribe();
+    let mut shutdown_rx3 = shutdown_tx.subscribe();
 
     // Channel for chat-line commands and settings.
-    let (command_tx, mut command_rx) = broadcast::channel(2);
+    let (command_tx, mut command_rx) = broadcast::channel::<String>(2);
 
-    // The TwitchIRCClient is built with either the default (read-only) or Twitch
-    // login credentials (username & OAuth token pair).
-    let (mut incoming_messages, client) = TwitchIRCClient::<
-        SecureTCPTransport,
-        StaticLoginCredentials,
-    >::new(set_client_config(config_path).await);
+    // Secrets typed at a prompt on the input line, the command task
+    // carries on with whatever asked for them.
+    let (answer_tx, mut answer_rx) = mpsc::unbounded_channel();
 
-    // TwitchIRCClient is thread safe, clone() can be called here.
-    // client2 is used to send user messages to the Twitch servers.
-    let client2 = client.clone();
+    // Every task that changes what is on screen asks the render task
+    // to draw a new frame, which is the only place the terminal is written.
+    let (redraw_tx, mut redraw_rx) = broadcast::channel(16);
+    let redraw_incoming = redraw_tx.clone();
+    let redraw_command = redraw_tx.clone();
+    redraw_tx.send(()).ok();
+
+    // Highlighted messages ring the terminal bell when the config says so.
+    let (bell_tx, mut bell_rx) = broadcast::channel(4);
+
+    // The client is replaced when the user logs in or out with :login
+    // and :logout, its incoming messages are then handed to the first
+    // task over this channel.
+    let client = Arc::new(RwLock::new(client));
+    let client2 = Arc::clone(&client);
+    let (reconnect_tx, mut reconnect_rx) = mpsc::unbounded_channel::<IncomingMessages>();
 
     let screen = AlternateScreen::from(stdout());
-    home_screen();
 
     // Start consuming incoming messages, otherwise they will back up.
     //
-    // First tokio task to listen for incoming server messages
-    // and format them as needed before printing them to the console.
+    // First tokio task to listen for incoming server messages,
+    // format them and add them to their channel's tab.
     let join_handle = tokio::spawn(async move {
+        // A notifier that fails once, say without notify-send installed,
+        // likely fails every time, so that's only shown the first time.
+        let mut notify_failed = false;
         loop {
             select! {
                 Some(message) = incoming_messages.recv() => {
-                    print_message(format_message(message).await, input_buffer2.read().await.to_string()).await;
-                    if input_buffer2.read().await.is_empty() {
-                        user_interface::empty_line();
+                    status_incoming.write().await.connection = match message {
+                        ServerMessage::Reconnect(_) => Connection::Reconnecting,
+                        _ => Connection::Connected,
+                    };
+                    let channel = message_channel(&message);
+                    let theme = themes_incoming.read().await.current().clone();
+                    // Copied out so the ignores aren't locked while waiting for the channels.
+                    let (ignored, show_hidden) = {
+                        let ignores = ignores.read().await;
+                        (ignores.is_ignored(&message), ignores.show_hidden)
+                    };
+                    if ignored {
+                        if show_hidden {
+                            let mut channels = channels_incoming.write().await;
+                            if let Some(tab) = channel.as_deref().and_then(|channel| channels.get_mut(channel)) {
+                                hide_message(&mut tab.messages, &theme);
+                            }
+                            redraw_incoming.send(()).ok();
+                        }
+                        continue;
+                    }
+                    // Whispers go to their own conversation, not any channel.
+                    if let ServerMessage::Whisper(whisper) = &message {
+                        let line = format_whisper(whisper, &config_incoming, &theme, &mut *emotes.write().await);
+                        channels_incoming.write().await.whisper(&whisper.sender.login, line);
+                        redraw_incoming.send(()).ok();
+                        continue;
+                    }
+                    let sender = match &message {
+                        ServerMessage::Privmsg(privmsg) => Some((
+                            privmsg.sender.login.clone(),
+                            ChatterInfo {
+                                name: privmsg.sender.name.clone(),
+                                badges: privmsg.badges.clone(),
+                                badge_info: privmsg.badge_info.clone(),
+                            },
+                        )),
+                        _ => None,
+                    };
+                    if let Some(channel) = &channel {
+                        let mut channels = channels_incoming.write().await;
+                        if let Some(tab) = channels.get_mut(channel) {
+                            if let Some((sender, info)) = sender {
+                                tab.saw_chatter(&sender);
+                                tab.saw_chatter_info(&sender, info);
+                            }
+                            update_channel(&message, tab, &config_incoming, &theme);
+                        }
+                        // Deleted messages are taken out of the mentions view too.
+                        update_mentions(&message, &mut channels, &config_incoming, &theme);
+                    }
+                    let login = status_incoming.read().await.login.clone();
+                    let highlighted = is_highlight(&message, &config_incoming, &login);
+                    let notification = match &message {
+                        ServerMessage::Privmsg(privmsg) if highlighted => {
+                            Some(Notification {
+                                channel: privmsg.channel_login.clone(),
+                                user: privmsg.sender.name.clone(),
+                                message: privmsg.message_text.clone(),
+                            })
+                        }
+                        _ => None,
+                    };
+                    if notification.is_some() && config_incoming.notifications.bell {
+                        bell_tx.send(()).ok();
+                    }
+                    let formatted = format_message(message, &config_incoming, &theme, &mut *emotes.write().await, &login, highlighted).await;
+                    if let Some(formatted) = formatted {
+                        let mut channels = channels_incoming.write().await;
+                        // Highlights are copied to the mentions view too.
+                        if let Some(notification) = &notification {
+                            let marker = theme.timestamp.paint(format!("#{}", notification.channel));
+                            channels.mention(&formatted, &notification.channel, &marker);
+                        }
+                        let tab = match &channel {
+                            Some(channel) => channels.get_mut(channel),
+                            None => channels.active_mut(),
+                        };
+                        match tab {
+                            Some(tab) => tab.messages.push(formatted),
+                            None => channels.notices.push(formatted),
+                        }
                     }
+                    if let Some(notification) = notification {
+                        for notifier in &notifiers {
+                            if let Err(error) = notifier.notify(&notification) {
+                                if !notify_failed {
+                                    notify_failed = true;
+                                    channels_incoming.write().await.notify(
+                                        format!("Couldn't send a notification: {}", error).red().to_string(),
+                                    );
+                                }
+                            }
+                        }
+                    }
+                    redraw_incoming.send(()).ok();
+                },
+                Some(new_incoming_messages) = reconnect_rx.recv() => {
+                    incoming_messages = new_incoming_messages;
                 },
                 // End process if sender message received.
                 _ = shutdown_rx.recv() => break,
@@ -84,102 +349,240 @@ pub async fn main() -> std::io::Result<()> {
     });
 
     // Use this channel to send/receive termion::Event::Key
-    let (input_tx, input_rx) = mpsc::channel();
+    let (input_tx, mut input_rx) = mpsc::unbounded_channel();
 
     // Second tokio task to listen to user input and outgoing chat messages.
     let join_handle2 = tokio::spawn(async move {
-        // Set terminal to raw mode to allow reading
-        // stdin one key at a time.
-        let mut stdout = stdout().into_raw_mode().unwrap();
-        let mut buffer_position = input_buffer.read().await.len();
-
         loop {
-            let key = loop {
-                match input_rx.try_recv() {
-                    Err(TryRecvError::Empty) => {}, // no op, keep trying to read from channel
-                    Err(TryRecvError::Disconnected) => unimplemented!(), // What should we do if one part of the channel disconnects?
-                    Ok(code) => break code,
-                }
-                task::yield_now().await;
+            let key = match input_rx.recv().await {
+                Some(key) => key,
+                None => break,
             };
 
+            // While a secret is asked for only typing and deleting work, so
+            // none of it ends up in the history or the kill ring. Esc cancels.
+            if input_buffer.read().await.prompt().is_some() {
+                let mut input = input_buffer.write().await;
+                match key {
+                    termion::event::Key::Char('\n') => {
+                        if let Some(answer) = input.answer() {
+                            answer_tx.send(answer).ok();
+                        }
+                    }
+                    termion::event::Key::Char('\t') => {}
+                    termion::event::Key::Char(c) => input.insert(c),
+                    termion::event::Key::Backspace => input.backspace(),
+                    termion::event::Key::Esc | termion::event::Key::Ctrl('g') => {
+                        input.cancel_prompt();
+                        drop(input);
+                        channels.write().await.notify("Cancelled.".to_string());
+                    }
+                    _ => {}
+                }
+                if key != config.keys.quit.0 {
+                    redraw_tx.send(()).ok();
+                    continue;
+                }
+            }
+
+            // While searching the history with Ctrl-r, typing edits the search
+            // and any other key accepts the match before doing what it does.
+            if input_buffer.read().await.is_searching() {
+                let mut input = input_buffer.write().await;
+                match key {
+                    termion::event::Key::Char('\n') => input.accept_search(),
+                    key if key == config.keys.search_history.0 => input.search_older(),
+                    termion::event::Key::Char(c) => input.search_insert(c),
+                    termion::event::Key::Backspace => input.search_backspace(),
+                    termion::event::Key::Esc | termion::event::Key::Ctrl('g') => {
+                        input.cancel_search()
+                    }
+                    _ => input.accept_search(),
+                }
+                if input.is_searching() {
+                    redraw_tx.send(()).ok();
+                    continue;
+                }
+            }
+
+            // While picking a message to reply to, Up and Down move through
+            // the chat and Enter starts a :reply to it on the input line.
+            let selecting = channels
+                .read()
+                .await
+                .active()
+                .is_some_and(|channel| channel.messages.selected().is_some());
+            if selecting {
+                let page = user_interface::chat_height().saturating_sub(1).max(1);
+                let mut channels = channels.write().await;
+                if let Some(channel) = channels.active_mut() {
+                    let messages = &mut channel.messages;
+                    match key {
+                        termion::event::Key::Up => messages.select_older(page),
+                        key if key == config.keys.reply.0 => messages.select_older(page),
+                        termion::event::Key::Down => messages.select_newer(page),
+                        termion::event::Key::Char('\n') => {
+                            if let Some(id) = messages.take_selection() {
+                                drop(channels);
+                                input_buffer.write().await.set_input(format!(":reply {} ", id));
+                            }
+                        }
+                        termion::event::Key::Esc | termion::event::Key::Ctrl('g') => {
+                            messages.take_selection();
+                        }
+                        _ => {}
+                    }
+                }
+                if key != config.keys.quit.0 {
+                    redraw_tx.send(()).ok();
+                    continue;
+                }
+            }
+
             // TODO: Look into thread spawn or tokio::Stdin, .next() poss blocking main thread.
-            // TODO: Also look into poss channel, maybe abstract some of the pattern 
+            // TODO: Also look into poss channel, maybe abstract some of the pattern
             // matching?
-            let first_char = input_buffer.read().await.chars().nth(0);
+            let keys = &config.keys;
             match key {
-                termion::event::Key::Char('\n') => {
-                    if !input_buffer.read().await.is_empty() {
-                        if first_char == Some(':') {
-                            // If the entered input buffer starts with a ':'
-                            // then the run_command function is executed,
-                            // parsing the command and running its logic.
-                            command_tx.send(()).ok();
-                        } else {
-                            send_user_message(
-                                user_name.read().await.as_str(),
-                                current_channel.read().await.as_str(),
-                                Arc::clone(&input_buffer),
-                                &client2,
-                            )
-                                .await;
+                // The keys from the config's [keys] table come before
+                // any other use of them.
+                key if key == keys.quit.0 => {
+                    // Send message to receivers to end process.
+                    shutdown_tx.send(()).ok();
+                    break;
+                }
+                // Ctrl-n and Ctrl-p cycle through the tabs.
+                key if key == keys.next_tab.0 => {
+                    channels.write().await.next();
+                }
+                key if key == keys.prev_tab.0 => {
+                    channels.write().await.prev();
+                }
+                key if key == keys.search_history.0 => input_buffer.write().await.search_older(),
+                // Replies are picked from a channel's own chat, not the
+                // mentions view or a conversation.
+                key if key == keys.reply.0 => {
+                    let page = user_interface::chat_height().saturating_sub(1).max(1);
+                    let mut channels = channels.write().await;
+                    if channels.is_showing_tab() {
+                        if let Some(channel) = channels.active_mut() {
+                            channel.messages.select_older(page);
                         }
-                        user_interface::empty_line();
                     }
                 }
-                termion::event::Key::Char(user_input) => {
-                    // write user input to the console
-                    // and save it to input_buffer
-                    if !input_buffer.read().await.is_empty() {
-                        write!(stdout, "{}", user_input).unwrap();
-                    } else {
-                        write!(stdout, "{}{}", termion::clear::AfterCursor, user_input)
-                            .unwrap();
+                key if key == keys.mentions.0 => channels.write().await.toggle_mentions(),
+                key if key == keys.whispers.0 => whispers_command(Arc::clone(&channels)).await,
+                key if key == keys.users.0 => {
+                    if let Some(channel) = channels.write().await.active_mut() {
+                        channel.toggle_viewers(None);
                     }
-                    input_buffer.write().await.push(user_input);
-                    buffer_position += 1;
                 }
-                termion::event::Key::Left => {
-                    if buffer_position == 0 {
-                    } else {
-                        write!(stdout, "{}", termion::cursor::Left(1)).unwrap();
-                        buffer_position -= 1;
+                // PageUp enters scroll mode, Home and End jump to the oldest
+                // message or back to following the chat while scrolled up,
+                // otherwise they move to the start or end of the input.
+                key if key == keys.scroll_up.0
+                    || key == keys.scroll_down.0
+                    || key == termion::event::Key::Home
+                    || key == termion::event::Key::End =>
+                {
+                    let page = user_interface::chat_height().saturating_sub(1).max(1);
+                    let mut channels = channels.write().await;
+                    let view = channels.view_mut();
+                    let scrolling = view.as_ref().is_some_and(|view| !view.is_following());
+                    match (key, view) {
+                        (key, Some(view)) if key == keys.scroll_up.0 => view.scroll_up(page),
+                        (key, Some(view)) if key == keys.scroll_down.0 => view.scroll_down(page),
+                        (termion::event::Key::Home, Some(view)) if scrolling => view.scroll_to_top(),
+                        (termion::event::Key::End, Some(view)) if scrolling => view.scroll_to_bottom(),
+                        (termion::event::Key::Home, _) => input_buffer.write().await.move_home(),
+                        (termion::event::Key::End, _) => input_buffer.write().await.move_end(),
+                        _ => {}
                     }
                 }
-                termion::event::Key::Right => {
-                    if buffer_position == input_buffer.read().await.len() {
-                    } else {
-                        write!(stdout, "{}", termion::cursor::Right(1)).unwrap();
-                        buffer_position += 1;
+                termion::event::Key::Char('\n') => {
+                    let line = input_buffer.write().await.submit();
+                    if line.starts_with(':') {
+                        // If the entered input buffer starts with a ':'
+                        // then the run_command function is executed,
+                        // parsing the command and running its logic.
+                        command_tx.send(line).ok();
+                    } else if !line.is_empty() {
+                        let user_name = status.read().await.login.clone();
+                        let theme = themes.read().await.current().clone();
+                        // In a conversation what's typed is whispered back.
+                        let partner = channels
+                            .read()
+                            .await
+                            .showing_whisper()
+                            .map(|conversation| conversation.partner.clone());
+                        if let Some(partner) = partner {
+                            send_whisper(
+                                &user_name,
+                                &partner,
+                                line,
+                                Arc::clone(&channels),
+                                &current(&client2).await,
+                                &config,
+                                &theme,
+                            )
+                            .await;
+                        } else {
+                            send_user_message(
+                                &user_name,
+                                Arc::clone(&channels),
+                                line,
+                                &current(&client2).await,
+                                &config,
+                                &theme,
+                                None,
+                            )
+                            .await;
+                        }
                     }
                 }
-                termion::event::Key::Backspace => {
-                    // Backspace does nothing unless the input_buffer
-                    // has characters to delete.
-                    if !input_buffer.read().await.is_empty() {
-                        input_buffer.write().await.pop();
-                        write!(
-                            stdout,
-                            "{}{}",
-                            termion::cursor::Left(1),
-                            termion::clear::AfterCursor
-                        )
-                            .unwrap();
-                        buffer_position -= 1;
-                        if input_buffer.read().await.is_empty() {
-                            user_interface::empty_line();
+                // Tab completes commands, channels and @mentions,
+                // pressing it again cycles through the other matches.
+                termion::event::Key::Char('\t') | termion::event::Key::BackTab => {
+                    let forward = key == termion::event::Key::Char('\t');
+                    if input_buffer.read().await.is_completing() {
+                        input_buffer.write().await.cycle_completion(forward);
+                    } else {
+                        let before_cursor = input_buffer.read().await.before_cursor().to_string();
+                        let completions = completions(&before_cursor, &*channels.read().await);
+                        if let Some((start, candidates)) = completions {
+                            input_buffer.write().await.complete(start, candidates);
                         }
                     }
                 }
-                termion::event::Key::Ctrl('q') => {
-                    // Send message to receivers to end process.
-                    shutdown_tx.send(()).ok();
-                    break;
+                termion::event::Key::Char(user_input) => {
+                    input_buffer.write().await.insert(user_input);
+                }
+                // Line editing, with emacs style bindings.
+                termion::event::Key::Left => input_buffer.write().await.move_left(),
+                termion::event::Key::Right => input_buffer.write().await.move_right(),
+                termion::event::Key::Backspace => input_buffer.write().await.backspace(),
+                termion::event::Key::Delete => input_buffer.write().await.delete(),
+                termion::event::Key::Ctrl('a') => input_buffer.write().await.move_home(),
+                termion::event::Key::Ctrl('e') => input_buffer.write().await.move_end(),
+                termion::event::Key::Alt('b') => input_buffer.write().await.move_word_left(),
+                termion::event::Key::Alt('f') => input_buffer.write().await.move_word_right(),
+                termion::event::Key::Ctrl('w') => input_buffer.write().await.kill_word_before(),
+                termion::event::Key::Ctrl('u') => input_buffer.write().await.kill_to_start(),
+                termion::event::Key::Ctrl('k') => input_buffer.write().await.kill_to_end(),
+                termion::event::Key::Ctrl('y') => input_buffer.write().await.yank(),
+                termion::event::Key::Alt('y') => input_buffer.write().await.yank_pop(),
+                // Sent messages and commands, Up and Down step through them
+                // and Ctrl-r searches them.
+                termion::event::Key::Up => input_buffer.write().await.history_prev(),
+                termion::event::Key::Down => input_buffer.write().await.history_next(),
+                // Alt-1 to Alt-9 jump straight to a tab.
+                termion::event::Key::Alt(number @ '1'..='9') => {
+                    let index = number as usize - '1' as usize;
+                    channels.write().await.select(index);
                 }
-
                 _ => {}
             }
-            stdout.lock().flush().unwrap();
+            redraw_tx.send(()).ok();
         }
     });
 
@@ -188,13 +591,31 @@ pub async fn main() -> std::io::Result<()> {
             select! {
                     // if a command ':' is found in a sent input buffer,
                     // call run_command to parse the input and handle the command
-                    Ok(_command) = command_rx.recv() => {
+                    Ok(command) = command_rx.recv() => {
                         run_command(
-                            Arc::clone(&input_buffer_lock),
-                            Arc::clone(&current_channel_read),
+                            command,
+                            Arc::clone(&channels_command),
+                            Arc::clone(&status_command),
+                            Arc::clone(&input_buffer_command),
+                            Arc::clone(&themes_command),
+                            Arc::clone(&ignores_command),
+                            &config_command,
+                            Arc::clone(&client),
+                            &reconnect_tx,
+                        ).await;
+                        redraw_command.send(()).ok();
+                },
+                    Some(answer) = answer_rx.recv() => {
+                        answer_prompt(
+                            answer,
+                            Arc::clone(&channels_command),
+                            Arc::clone(&status_command),
+                            Arc::clone(&input_buffer_command),
                             &config_path,
-                            &client
-                        ).await
+                            Arc::clone(&client),
+                            &reconnect_tx,
+                        ).await;
+                        redraw_command.send(()).ok();
                 },
                      // End process if sender message received.
                     _ = shutdown_rx2.recv() => break,
@@ -202,18 +623,40 @@ pub async fn main() -> std::io::Result<()> {
         }
     });
 
+    // Fourth tokio task draws the screen, on request from the other
+    // tasks and whenever the terminal is resized.
+    let join_handle4 = tokio::spawn(async move {
+        let mut screen = Screen::new().unwrap();
+        let mut resized = signal(SignalKind::window_change()).unwrap();
+        loop {
+            select! {
+                // A lagged receiver only means several redraws
+                // were asked for, one frame covers all of them.
+                _ = redraw_rx.recv() => {},
+                Ok(_) = bell_rx.recv() => screen.bell().unwrap(),
+                Some(_) = resized.recv() => screen.resize().unwrap(),
+                _ = shutdown_rx3.recv() => break,
+            };
+            let theme = themes_render.read().await.current().clone();
+            let images = emotes_render.write().await.take_images();
+            screen.send_images(&images).unwrap();
+            let channels = channels_render.read().await;
+            let input_buffer = input_buffer_render.read().await;
+            let status = status_render.read().await;
+            screen.render(&channels, &input_buffer, &status, &theme).unwrap();
+        }
+    });
+
     let input_reader_tx = input_tx.clone();
     let _input_reader = std::thread::spawn(move || {
         loop {
             let keys = std::io::stdin().keys();
-            for key in keys {
-                // `key` can be an Err variant, are we gonna handle those?
-                if let Ok(code) = key {
-                    if let Err(_error) = input_reader_tx.send(code) {
-                        // What do we do if an error happens?
-                        unimplemented!();
-                    };
-                }
+            // `key` can be an Err variant, are we gonna handle those?
+            for code in keys.flatten() {
+                if let Err(_error) = input_reader_tx.send(code) {
+                    // What do we do if an error happens?
+                    unimplemented!();
+                };
             }
         }
     });
@@ -221,7 +664,7 @@ pub async fn main() -> std::io::Result<()> {
     // Keep the tokio executor alive.
     // If you return instead of waiting,
     // the background task will exit.
-    futures::join!(join_handle, join_handle2, join_handle3);
+    let _ = futures::join!(join_handle, join_handle2, join_handle3, join_handle4);
     screen.lock().flush().unwrap();
     Ok(())
 }
@@ -1,42 +1,171 @@
-use chrono::{DateTime, Timelike, Utc};
-use owo_colors::{OwoColorize, Rgb};
+use crate::badges::render_badges;
+use crate::channels::{Channel, Channels};
+use crate::emotes::Emotes;
+use crate::moderation::format_duration;
+use crate::scrollback::{Line, Scrollback};
+use crate::theme::{Style, Theme};
+use crate::user_config::UserConfig;
+use crate::Client;
+use chrono::{DateTime, Utc};
+use owo_colors::OwoColorize;
 use std::{
-    io::{stdout, Write},
     sync::Arc,
+    time::{Duration, Instant},
 };
-use tokio::sync::RwLock;
+use tokio::{sync::RwLock, time::sleep};
 use twitch_irc::message::{
-    ClearChatAction, HostTargetAction, RGBColor, ServerMessage, UserNoticeEvent,
-};
-use twitch_irc::{
-    login::StaticLoginCredentials, /*ClientConfig,*/ SecureTCPTransport, TwitchIRCClient,
+    ClearChatAction, HostTargetAction, IRCMessage, IRCTags, ServerMessage, UserNoticeEvent,
+    WhisperMessage,
 };
 
-// TODO: Look into adding emotes.
+/// Characters of the message replied to that are shown above a reply.
+const QUOTE_LENGTH: usize = 60;
+
+/// The IRC numeric Twitch lists a channel's chatters with.
+const NAMES_REPLY: &str = "353";
+
 // TODO: Text alignment.
 // TODO: Window sizing.
 // TODO: Scrolling through chat history.
-// TODO: Look into chat replies from users.
 
-pub async fn format_message(message: ServerMessage) -> Option<String> {
+/// The channel a server message belongs to, used to route it to that
+/// channel's tab. Messages without a channel go to the tab on screen.
+pub fn message_channel(message: &ServerMessage) -> Option<String> {
     match message {
-        // User chat messages:
+        ServerMessage::Privmsg(msg) => Some(msg.channel_login.clone()),
+        ServerMessage::ClearChat(msg) => Some(msg.channel_login.clone()),
+        ServerMessage::ClearMsg(msg) => Some(msg.channel_login.clone()),
+        ServerMessage::HostTarget(msg) => Some(msg.channel_login.clone()),
+        ServerMessage::Join(msg) => Some(msg.channel_login.clone()),
+        ServerMessage::Part(msg) => Some(msg.channel_login.clone()),
+        ServerMessage::Notice(msg) => msg.channel_login.clone(),
+        ServerMessage::RoomState(msg) => Some(msg.channel_login.clone()),
+        ServerMessage::UserNotice(msg) => Some(msg.channel_login.clone()),
+        ServerMessage::UserState(msg) => Some(msg.channel_login.clone()),
+        // The list of who's in a channel Twitch sends on joining it.
+        message if message.source().command == NAMES_REPLY => message
+            .source()
+            .params
+            .get(2)
+            .map(|channel| channel.trim_start_matches('#').to_string()),
+        _ => None,
+    }
+}
+
+/// Whether `user_login` is us, logged in as `login`. Reading chat
+/// anonymously, Twitch knows us as justinfan and some number.
+fn is_own_login(user_login: &str, login: &str) -> bool {
+    match login {
+        "" => user_login.starts_with("justinfan"),
+        login => user_login == login,
+    }
+}
+
+/// Whether a chat message should be highlighted: it's from one of the
+/// chatters in `[notifications].users`, contains one of the highlight words
+/// for its channel or our own `login` as a whole word whatever its case, or
+/// matches one of the patterns.
+pub fn is_highlight(message: &ServerMessage, config: &UserConfig, login: &str) -> bool {
+    let prvmsg = match message {
+        ServerMessage::Privmsg(prvmsg) => prvmsg,
+        _ => return false,
+    };
+    let notifications = &config.notifications;
+    let from_user = notifications.users.iter().any(|user| {
+        user.trim_start_matches('@')
+            .eq_ignore_ascii_case(&prvmsg.sender.login)
+    });
+    let highlights = config.highlights(&prvmsg.channel_login);
+    let own_name = notifications.own_name && !login.is_empty();
+    let has_word = prvmsg
+        .message_text
+        .split(|c: char| !c.is_alphanumeric() && c != '_')
+        .any(|word| {
+            highlights
+                .iter()
+                .any(|highlight| highlight.eq_ignore_ascii_case(word))
+                || (own_name && login.eq_ignore_ascii_case(word))
+        });
+    let matches = notifications
+        .patterns
+        .iter()
+        .any(|pattern| pattern.0.is_match(&prvmsg.message_text));
+    from_user || has_word || matches
+}
+
+/// A server message as a line of chat, in the colours of `theme`, and in
+/// the highlight style when `highlighted`, see `is_highlight`. Messages
+/// that aren't shown give `None`.
+pub async fn format_message(
+    message: ServerMessage,
+    config: &UserConfig,
+    theme: &Theme,
+    emotes: &mut Emotes,
+    login: &str,
+    highlighted: bool,
+) -> Option<Line> {
+    let style = match &message {
+        ServerMessage::ClearChat(_) => &theme.ban,
+        ServerMessage::UserNotice(_) => &theme.sub_event,
+        _ => &theme.notice,
+    };
+    let line = match message {
+        // User chat messages, the only ones with a timestamp.
         ServerMessage::Privmsg(prvmsg) => {
-            let user_name_color = prvmsg.name_color.unwrap_or(RGBColor {
-                r: 255,
-                g: 255,
-                b: 255,
+            let picked = prvmsg.name_color.as_ref().map(|color| (color.r, color.g, color.b));
+            let name_style = theme.name_style(&prvmsg.sender.login, picked);
+            // Actions, sent with /me, are in italics and the sender's
+            // colour unless they're highlighted.
+            let style = match (highlighted, prvmsg.is_action) {
+                (true, action) => Style {
+                    italic: theme.highlight.italic || action,
+                    ..theme.highlight.clone()
+                },
+                (false, true) => action_style(&name_style),
+                (false, false) => Style::default(),
+            };
+            let text = emotes.render(
+                &prvmsg.message_text,
+                &prvmsg.emotes,
+                &prvmsg.channel_login,
+                &style,
+                theme,
+            );
+            let badges = if config.show_badges(&prvmsg.channel_login) {
+                render_badges(&prvmsg.badges, &config.badges.glyphs, theme.color_support)
+            } else {
+                String::new()
+            };
+            // Replies carry the message they reply to in their tags.
+            let tag = |name: &str| prvmsg.source.tags.0.get(name).cloned().flatten();
+            let quote = match (
+                tag("reply-parent-display-name"),
+                tag("reply-parent-msg-body"),
+            ) {
+                (Some(name), Some(body)) => Some(quote(&name, &body, theme)),
+                _ => None,
+            };
+            return Some(Line {
+                prefix: format!(
+                    " {} {}{}{} ",
+                    theme.timestamp.paint(
+                        prvmsg
+                            .server_timestamp
+                            .format(config.timestamp_format(&prvmsg.channel_login))
+                    ),
+                    badges,
+                    name_style.paint(&prvmsg.sender.name),
+                    if prvmsg.is_action { "" } else { ":" },
+                ),
+                body: text,
+                message_id: Some(prvmsg.message_id),
+                sender: Some(prvmsg.sender.login),
+                original: Some(prvmsg.message_text),
+                redacted: false,
+                quote,
+                hidden: 0,
+                channel: None,
             });
-            Some(format!(
-                " {} {}: {}\n",
-                prvmsg.server_timestamp.format("%H:%M").dimmed(),
-                prvmsg.sender.name.bold().color(Rgb(
-                    user_name_color.r,
-                    user_name_color.g,
-                    user_name_color.b
-                )),
-                prvmsg.message_text
-            ))
         }
 
         // User time-outs, bans, and a cleared chat history messages:
@@ -53,7 +182,7 @@ pub async fn format_message(message: ServerMessage) -> Option<String> {
                 name = user_login,
                 seconds = timeout_length.as_secs()
             )),
-            ClearChatAction::ChatCleared => Some(format!("Chat has been cleared.")),
+            ClearChatAction::ChatCleared => Some("Chat has been cleared.".to_string()),
         },
 
         // Channel-hosting
@@ -68,7 +197,7 @@ pub async fn format_message(message: ServerMessage) -> Option<String> {
                     hosted_channel_login, viewer_count
                 ))
             }
-            HostTargetAction::HostModeOff { .. } => Some(format!("No longer hosting.")),
+            HostTargetAction::HostModeOff { .. } => Some("No longer hosting.".to_string()),
         },
 
         // Event messages, raids, subs:
@@ -77,9 +206,8 @@ pub async fn format_message(message: ServerMessage) -> Option<String> {
             UserNoticeEvent::SubOrResub {
                 is_resub,
                 cumulative_months,
-                streak_months,
                 sub_plan,
-                sub_plan_name,
+                ..
             } => {
                 if is_resub {
                     Some(format!(
@@ -97,10 +225,7 @@ pub async fn format_message(message: ServerMessage) -> Option<String> {
                 }
             }
 
-            UserNoticeEvent::Raid {
-                viewer_count,
-                profile_image_url,
-            } => Some(format!(
+            UserNoticeEvent::Raid { viewer_count, .. } => Some(format!(
                 "{name} raided with {viewers} viewers!",
                 name = usernotice.sender.name,
                 viewers = viewer_count,
@@ -108,11 +233,10 @@ pub async fn format_message(message: ServerMessage) -> Option<String> {
 
             UserNoticeEvent::SubGift {
                 is_sender_anonymous,
-                cumulative_months,
                 recipient,
                 sub_plan,
-                sub_plan_name,
                 num_gifted_months,
+                ..
             } => {
                 if is_sender_anonymous {
                     Some(format!(
@@ -130,35 +254,30 @@ pub async fn format_message(message: ServerMessage) -> Option<String> {
             UserNoticeEvent::SubMysteryGift {
                 mass_gift_count,
                 sender_total_gifts,
-                sub_plan,
+                ..
             } => Some(format!(
                 "{} is gifting {} subs! They've gifted a total of {}!",
                 usernotice.sender.name, mass_gift_count, sender_total_gifts,
             )),
 
             UserNoticeEvent::AnonSubMysteryGift {
-                mass_gift_count,
-                sub_plan,
+                mass_gift_count, ..
             } => Some(format!(
                 "An anonymous user is gifting {} subs!",
                 mass_gift_count
             )),
 
-            UserNoticeEvent::GiftPaidUpgrade {
-                gifter_login,
-                gifter_name,
-                promotion,
-            } => Some(format!(
+            UserNoticeEvent::GiftPaidUpgrade { gifter_name, .. } => Some(format!(
                 "{} continued their gifted sub from {}!",
                 usernotice.sender.name, gifter_name
             )),
 
-            UserNoticeEvent::AnonGiftPaidUpgrade { promotion } => Some(format!(
+            UserNoticeEvent::AnonGiftPaidUpgrade { .. } => Some(format!(
                 "{} continued their gifted sub from an anonymous user!",
                 usernotice.sender.name
             )),
 
-            UserNoticeEvent::Ritual { ritual_name } => Some(format!(
+            UserNoticeEvent::Ritual { .. } => Some(format!(
                 "{} is new to chat! Say hi!",
                 usernotice.sender.name
             )),
@@ -174,59 +293,487 @@ pub async fn format_message(message: ServerMessage) -> Option<String> {
         // Simple server messages related to user and moderator actions and
         // server-side messages:
 
-        // TODO: Look into and proper message removal.
-        // Will probably need to buffer n incoming messages.
-        ServerMessage::ClearMsg(_) => Some(format!("Message deleted.\n")),
-        ServerMessage::GlobalUserState(_) => Some(format!("Login successful!\n")),
-        ServerMessage::Part(_) => Some(format!("Departed chat.\n")),
-        ServerMessage::Notice(notice) => Some(format!("{}\n", notice.message_text)),
-        ServerMessage::Join(join) => Some(format!("Joined {}'s chat!\n\n", join.channel_login)),
+        // Deleted messages are redacted where they are, see `update_channel`.
+        ServerMessage::ClearMsg(_) => None,
+        ServerMessage::GlobalUserState(_) => Some("Login successful!".to_string()),
+        // Everyone else's joins and parts only go into the viewer list.
+        ServerMessage::Part(part) if !is_own_login(&part.user_login, login) => None,
+        ServerMessage::Join(join) if !is_own_login(&join.user_login, login) => None,
+        ServerMessage::Part(part) if config.hide_joins_parts(&part.channel_login) => None,
+        ServerMessage::Join(join) if config.hide_joins_parts(&join.channel_login) => None,
+        ServerMessage::Part(_) => Some("Departed chat.".to_string()),
+        ServerMessage::Notice(notice) => Some(notice.message_text),
+        ServerMessage::Join(join) => Some(format!("Joined {}'s chat!", join.channel_login)),
 
         // Any other events that do not need to be verbose
         _ => None,
+    };
+    line.map(|line| Line::from(style.paint(line)))
+}
+
+/// Count an ignored message on the line standing in for the ones right
+/// before it, or on a new one.
+pub fn hide_message(messages: &mut Scrollback, theme: &Theme) {
+    let line = match messages.last_mut() {
+        Some(line) if line.hidden > 0 => line,
+        _ => {
+            messages.push(Line::from(String::new()));
+            messages.last_mut().unwrap()
+        }
+    };
+    line.hidden += 1;
+    line.body = theme.deleted.paint(match line.hidden {
+        1 => "1 hidden message".to_string(),
+        hidden => format!("{} hidden messages", hidden),
+    });
+}
+
+/// The row above a reply, with the start of the message it replies to.
+fn quote(name: &str, body: &str, theme: &Theme) -> String {
+    let mut snippet: String = body.chars().take(QUOTE_LENGTH).collect();
+    if snippet.len() < body.len() {
+        snippet = format!("{}…", snippet.trim_end());
     }
+    theme.reply.paint(format!("   ↱ @{}: {}", name, snippet))
 }
 
-pub async fn print_message(server_message: Option<String>, input_buffer: String) {
-    // When a server message is printed, the user's current input
-    // and the UI need to be taken into account.
-    //
-    // The current line must be cleared first, and carraige return called.
-    // The message is then printed, and carraige return and newline are called.
-    // If the user had any unsent input, that is saved and printed
-    // back to the console, then the cursor is restored to it's previous
-    // position.
-    if let Some(message) = server_message {
-        print!(
-            "{clear}\r{msg}\r\n",
-            clear = termion::clear::CurrentLine,
-            msg = message,
-        );
-        print!("> {}", input_buffer);
+/// Keep up with what a server message changes in its channel: whether
+/// we moderate it, its chat modes, who's in it and which of its messages
+/// were deleted.
+pub fn update_channel(
+    message: &ServerMessage,
+    channel: &mut Channel,
+    config: &UserConfig,
+    theme: &Theme,
+) {
+    match message {
+        ServerMessage::UserState(userstate) => {
+            let has = |name: &str| userstate.badges.iter().any(|badge| badge.name == name);
+            channel.moderator = has("moderator") || has("broadcaster");
+            channel.vip = has("vip");
+        }
+        ServerMessage::RoomState(roomstate) => channel.room_state.update(roomstate),
+        ServerMessage::ClearMsg(_) | ServerMessage::ClearChat(_) => {
+            let show_original = config.show_deleted && channel.moderator;
+            redact(message, channel.messages.lines_mut(), show_original, theme);
+        }
+        ServerMessage::Join(join) => {
+            channel.viewers.insert(join.user_login.clone());
+        }
+        ServerMessage::Part(part) => {
+            channel.viewers.remove(&part.user_login);
+        }
+        message if message.source().command == NAMES_REPLY => {
+            if let Some(names) = message.source().params.get(3) {
+                channel
+                    .viewers
+                    .extend(names.split_whitespace().map(str::to_string));
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Redact the copies in the mentions view of the messages a `CLEARMSG` or
+/// `CLEARCHAT` deletes, like `update_channel` does in their channel.
+pub fn update_mentions(
+    message: &ServerMessage,
+    channels: &mut Channels,
+    config: &UserConfig,
+    theme: &Theme,
+) {
+    let channel = match message {
+        ServerMessage::ClearMsg(clearmsg) => &clearmsg.channel_login,
+        ServerMessage::ClearChat(clearchat) => &clearchat.channel_login,
+        _ => return,
+    };
+    let show_original =
+        config.show_deleted && channels.get(channel).is_some_and(|channel| channel.moderator);
+    let lines = channels
+        .mentions
+        .lines_mut()
+        .filter(|line| line.channel.as_ref() == Some(channel));
+    redact(message, lines, show_original, theme);
+}
+
+/// Replace the text of the `lines` a `CLEARMSG` or `CLEARCHAT` deletes,
+/// keeping it struck through with `show_original`.
+fn redact<'a>(
+    message: &ServerMessage,
+    lines: impl Iterator<Item = &'a mut Line>,
+    show_original: bool,
+    theme: &Theme,
+) {
+    let deleted = |line: &Line| match message {
+        ServerMessage::ClearMsg(clearmsg) => {
+            line.message_id.as_ref() == Some(&clearmsg.message_id)
+        }
+        ServerMessage::ClearChat(clearchat) => match &clearchat.action {
+            ClearChatAction::UserBanned { user_login, .. }
+            | ClearChatAction::UserTimedOut { user_login, .. } => {
+                line.sender.as_ref() == Some(user_login)
+            }
+            ClearChatAction::ChatCleared => true,
+        },
+        _ => false,
+    };
+    for line in lines {
+        if line.redacted || line.sender.is_none() || !deleted(line) {
+            continue;
+        }
+        line.redacted = true;
+        line.body = match &line.original {
+            Some(original) if show_original => {
+                let mut style = theme.deleted.clone();
+                style.strikethrough = true;
+                style.paint(original)
+            }
+            _ => theme.deleted.paint("<message deleted>"),
+        };
+    }
+}
+
+/// How the text of an action is drawn, in italics in the sender's
+/// `name_style`.
+fn action_style(name_style: &Style) -> Style {
+    Style {
+        italic: true,
+        ..name_style.clone()
+    }
+}
+
+/// A whisper to us as a line of its conversation, timestamped when it
+/// arrived as whispers carry no time of their own.
+pub fn format_whisper(
+    whisper: &WhisperMessage,
+    config: &UserConfig,
+    theme: &Theme,
+    emotes: &mut Emotes,
+) -> Line {
+    let picked = whisper.name_color.as_ref().map(|color| (color.r, color.g, color.b));
+    let name_style = theme.name_style(&whisper.sender.login, picked);
+    // Only global emotes work outside a channel.
+    let text = emotes.render(
+        &whisper.message_text,
+        &whisper.emotes,
+        "",
+        &Style::default(),
+        theme,
+    );
+    let badges = if config.badges.show {
+        render_badges(&whisper.badges, &config.badges.glyphs, theme.color_support)
+    } else {
+        String::new()
+    };
+    let now: DateTime<Utc> = Utc::now();
+    Line {
+        prefix: format!(
+            " {} {}{}: ",
+            theme.timestamp.paint(now.format(&config.timestamp_format.0)),
+            badges,
+            name_style.paint(&whisper.sender.name),
+        ),
+        body: text,
+        message_id: None,
+        sender: Some(whisper.sender.login.clone()),
+        original: Some(whisper.message_text.clone()),
+        redacted: false,
+        quote: None,
+        hidden: 0,
+        channel: None,
+    }
+}
+
+/// Whisper `message` to `partner` and switch to the conversation with
+/// them, where it's added as Twitch doesn't echo it back either.
+pub async fn send_whisper(
+    username: &str,
+    partner: &str,
+    message: String,
+    channels: Arc<RwLock<Channels>>,
+    client: &Client,
+    config: &UserConfig,
+    theme: &Theme,
+) {
+    let partner = partner.trim_start_matches('@').to_lowercase();
+    {
+        let mut channels = channels.write().await;
+        if username.is_empty() {
+            channels.notify(
+                "You are not logged in, enter :login <username> to whisper."
+                    .red()
+                    .to_string(),
+            );
+            return;
+        }
+        channels.open_whisper(&partner);
     }
-    stdout().flush().unwrap();
+    let username = username.to_string();
+    let timestamp_format = config.timestamp_format.0.clone();
+    let theme = theme.clone();
+    let client = client.clone();
+    // Whispers are sent as a chat command, to Twitch's own channel, from a
+    // task of its own so nothing waits on the connection.
+    tokio::spawn(async move {
+        let sent = client
+            .privmsg("jtv".to_string(), format!("/w {} {}", partner, message))
+            .await;
+        let mut channels = channels.write().await;
+        if let Err(error) = sent {
+            channels.notify(format!("Couldn't send that: {}", error).red().to_string());
+            return;
+        }
+        let now: DateTime<Utc> = Utc::now();
+        let line = Line {
+            prefix: format!(
+                " {} {}: ",
+                theme.timestamp.paint(now.format(&timestamp_format)),
+                theme.own_message.paint(&username),
+            ),
+            body: message.clone(),
+            message_id: None,
+            sender: Some(username),
+            original: Some(message),
+            redacted: false,
+            quote: None,
+            hidden: 0,
+            channel: None,
+        };
+        channels.whisper(&partner, line);
+    });
 }
 
+/// Send a chat message to the channel on screen and add it to that
+/// channel's messages, Twitch does not echo our own messages back. Nothing
+/// is sent from the mentions view.
+/// `reply_to` is the id of the message it replies to. A message starting
+/// with `/me` is sent as an action.
+///
+/// It's sent from a task of its own, so nothing waits on the connection.
+/// In slow mode a message sent too soon after the last one would only be
+/// turned down, so that task waits until Twitch takes it.
 pub async fn send_user_message(
     username: &str,
-    current_channel: &str,
-    input_buffer: Arc<RwLock<String>>,
-    client: &TwitchIRCClient<SecureTCPTransport, StaticLoginCredentials>,
+    channels: Arc<RwLock<Channels>>,
+    message: String,
+    client: &Client,
+    config: &UserConfig,
+    theme: &Theme,
+    reply_to: Option<String>,
 ) {
-    let mut buffer = input_buffer.write().await;
-    let now: DateTime<Utc> = Utc::now();
-    client
-        .privmsg(current_channel.to_owned(), buffer.to_owned())
-        .await
-        .unwrap();
-    print!(
-        "{}\r {}:{} {}: {}\n\n",
-        termion::clear::CurrentLine,
-        now.hour().dimmed(),
-        now.minute().dimmed(),
-        username.bold(),
-        buffer.to_string()
-    );
-    buffer.clear();
-    print!("{}\r> ", termion::clear::CurrentLine);
+    let mut channels_lock = channels.write().await;
+    // The mentions come from every channel, so there's none to send to.
+    if channels_lock.is_showing_mentions() {
+        channels_lock.notify(
+            "Go back to a tab to chat, Up brings your message back."
+                .red()
+                .to_string(),
+        );
+        return;
+    }
+    if username.is_empty() {
+        channels_lock.notify(
+            "You are not logged in, enter :login <username> to chat."
+                .red()
+                .to_string(),
+        );
+        return;
+    }
+    let (message, action) = match message.strip_prefix("/me") {
+        Some(action) if action.trim().is_empty() => {
+            channels_lock.notify(
+                "Enter what you're doing after /me, like /me waves."
+                    .red()
+                    .to_string(),
+            );
+            return;
+        }
+        Some(action) if action.starts_with(' ') => (action[1..].to_string(), true),
+        _ => (message, false),
+    };
+    let channel = match channels_lock.active_mut() {
+        Some(channel) => channel,
+        None => return,
+    };
+    let now = Instant::now();
+    let wait = channel.slow_mode_wait(now);
+    channel.sent_message(now + wait);
+    let outgoing = Outgoing {
+        username: username.to_string(),
+        channel: channel.name.clone(),
+        message,
+        action,
+        reply_to,
+        timestamp_format: config.timestamp_format(&channel.name).to_string(),
+        theme: theme.clone(),
+    };
+    if !wait.is_zero() {
+        let seconds = wait.as_secs() + u64::from(wait.subsec_nanos() > 0);
+        channel.messages.push(theme.notice.paint(format!(
+            "Slow mode is on, sending your message in {}.",
+            format_duration(Duration::from_secs(seconds))
+        )));
+    }
+    drop(channels_lock);
+    let client = client.clone();
+    tokio::spawn(async move {
+        if !wait.is_zero() {
+            sleep(wait).await;
+        }
+        outgoing.send(channels, &client).await;
+    });
+}
+
+/// A chat message of ours, with everything needed to send it and show
+/// it in its channel later on.
+struct Outgoing {
+    username: String,
+    channel: String,
+    message: String,
+    /// Sent with `/me`.
+    action: bool,
+    reply_to: Option<String>,
+    timestamp_format: String,
+    theme: Theme,
+}
+
+impl Outgoing {
+    async fn send(self, channels: Arc<RwLock<Channels>>, client: &Client) {
+        let theme = &self.theme;
+        // The channel may have been left while the message waited.
+        let quote = match channels.read().await.get(&self.channel) {
+            Some(channel) => self
+                .reply_to
+                .as_deref()
+                .and_then(|parent| channel.messages.find(parent))
+                .and_then(|parent| {
+                    Some(quote(parent.sender.as_ref()?, parent.original.as_ref()?, theme))
+                }),
+            None => return,
+        };
+        // Sending waits for the connection, which mustn't hold up the
+        // screen or incoming messages.
+        // An action's text is wrapped in a CTCP ACTION, the way /me sends it.
+        let text = if self.action {
+            format!("\u{1}ACTION {}\u{1}", self.message)
+        } else {
+            self.message.clone()
+        };
+        let sent = client
+            .send_message(chat_message(&self.channel, text, self.reply_to))
+            .await;
+        let now: DateTime<Utc> = Utc::now();
+        let mut channels = channels.write().await;
+        let channel = match channels.get_mut(&self.channel) {
+            Some(channel) => channel,
+            None => return,
+        };
+        // Only messages that went out are shown as ours.
+        if let Err(error) = sent {
+            let notice = format!("Couldn't send that: {}", error).red().to_string();
+            channel.messages.push(notice);
+            return;
+        }
+        let (separator, body) = if self.action {
+            (" ", action_style(&theme.own_message).paint(&self.message))
+        } else {
+            (": ", self.message.clone())
+        };
+        channel.messages.push(Line {
+            prefix: format!(
+                " {} {}{}",
+                theme.timestamp.paint(now.format(&self.timestamp_format)),
+                theme.own_message.paint(&self.username),
+                separator,
+            ),
+            body,
+            message_id: None,
+            sender: Some(self.username),
+            original: Some(self.message),
+            redacted: false,
+            quote,
+            hidden: 0,
+            channel: None,
+        });
+    }
+}
+
+/// The `PRIVMSG` for a chat message, tagged with the message it replies
+/// to. The client's own `say_in_response` puts `. ` in front of the text,
+/// which would be sent along with it.
+fn chat_message(channel: &str, text: String, reply_to: Option<String>) -> IRCMessage {
+    let mut tags = IRCTags::new();
+    if let Some(id) = reply_to {
+        tags.0.insert("reply-parent-msg-id".to_string(), Some(id));
+    }
+    IRCMessage::new(
+        tags,
+        None,
+        "PRIVMSG".to_string(),
+        vec![format!("#{}", channel), text],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+    use twitch_irc::message::IRCMessage;
+
+    fn privmsg(sender: &str, channel: &str, text: &str) -> ServerMessage {
+        let raw = format!(
+            "@badge-info=;badges=;color=;display-name={sender};emotes=;id=1;room-id=2;\
+             tmi-sent-ts=1600000000000;user-id=3 :{sender}!{sender}@{sender}.tmi.twitch.tv \
+             PRIVMSG #{channel} :{text}",
+            sender = sender,
+            channel = channel,
+            text = text
+        );
+        ServerMessage::try_from(IRCMessage::parse(&raw).unwrap()).unwrap()
+    }
+
+    fn config(toml: &str) -> UserConfig {
+        toml::from_str(&format!("username = \"me\"\n{}", toml)).unwrap()
+    }
+
+    #[test]
+    fn highlights_our_own_name_as_a_whole_word() {
+        let config = config("");
+        let highlight = |text| is_highlight(&privmsg("bob", "xqc", text), &config, "me");
+        assert!(highlight("hi ME!"));
+        assert!(highlight("@Me what"));
+        assert!(!highlight("meme"));
+        assert!(!highlight("some_me"));
+        // Anonymously there's no name to look for.
+        assert!(!is_highlight(&privmsg("bob", "xqc", "hi"), &config, ""));
+        let config = self::config("[notifications]\nown_name = false");
+        assert!(!is_highlight(&privmsg("bob", "xqc", "hi me"), &config, "me"));
+    }
+
+    #[test]
+    fn highlights_words_users_and_patterns() {
+        let config = config(
+            r#"
+[notifications]
+highlights = ["Tuitch"]
+users = ["@Alice"]
+patterns = ["^!\\w+"]
+
+[channels.forsen]
+highlights = ["rust"]
+"#,
+        );
+        let highlight = |sender, channel, text| {
+            is_highlight(&privmsg(sender, channel, text), &config, "me")
+        };
+        assert!(highlight("bob", "xqc", "tuitch is nice"));
+        assert!(!highlight("bob", "xqc", "tuitches"));
+        assert!(highlight("alice", "xqc", "anything"));
+        assert!(highlight("bob", "xqc", "!commands"));
+        assert!(!highlight("bob", "xqc", "not !commands"));
+        // A channel's own words replace the others there.
+        assert!(highlight("bob", "forsen", "RUST"));
+        assert!(!highlight("bob", "forsen", "tuitch is nice"));
+        assert!(!highlight("bob", "xqc", "rust"));
+    }
 }
@@ -0,0 +1,221 @@
+use crate::channels::Channels;
+use crate::Client;
+use owo_colors::OwoColorize;
+use std::{sync::Arc, time::Duration};
+use tokio::sync::RwLock;
+
+/// How a chat mode is changed: turned on with Twitch's default, on with
+/// a duration of our own, or off.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Setting {
+    On,
+    For(Duration),
+    Off,
+}
+
+impl Setting {
+    /// `on`, `off` or a duration, anything else is `None`.
+    pub fn parse(text: &str) -> Option<Setting> {
+        match text.to_lowercase().as_str() {
+            "on" => Some(Setting::On),
+            "off" => Some(Setting::Off),
+            text => parse_duration(text).map(Setting::For),
+        }
+    }
+}
+
+/// Something a moderator does in the channel on screen.
+#[derive(Debug, PartialEq)]
+pub enum ModAction {
+    Timeout {
+        user: String,
+        duration: Duration,
+        reason: Option<String>,
+    },
+    Ban {
+        user: String,
+        reason: Option<String>,
+    },
+    Unban(String),
+    Delete(String),
+    Slow(Setting),
+    /// Only followers can chat, those who have followed for the duration.
+    Followers(Setting),
+    EmoteOnly(bool),
+    Subscribers(bool),
+    Clear,
+}
+
+impl ModAction {
+    /// The chat command Twitch runs it as, which is all the client's own
+    /// `ban` and `timeout` send too.
+    fn chat_command(&self) -> String {
+        let with_reason = |command: String, reason: &Option<String>| match reason {
+            Some(reason) => format!("{} {}", command, reason),
+            None => command,
+        };
+        let switch = |name: &str, on: bool| {
+            if on {
+                format!("/{}", name)
+            } else {
+                format!("/{}off", name)
+            }
+        };
+        match self {
+            ModAction::Timeout {
+                user,
+                duration,
+                reason,
+            } => with_reason(format!("/timeout {} {}", user, duration.as_secs()), reason),
+            ModAction::Ban { user, reason } => with_reason(format!("/ban {}", user), reason),
+            ModAction::Unban(user) => format!("/unban {}", user),
+            ModAction::Delete(id) => format!("/delete {}", id),
+            ModAction::Slow(Setting::On) => "/slow".to_string(),
+            ModAction::Slow(Setting::For(duration)) => format!("/slow {}", duration.as_secs()),
+            ModAction::Slow(Setting::Off) => "/slowoff".to_string(),
+            ModAction::Followers(Setting::On) => "/followers".to_string(),
+            // Followers-only mode counts in whole minutes, rounded up so
+            // `30s` isn't sent as no time at all.
+            ModAction::Followers(Setting::For(duration)) => {
+                format!("/followers {}m", duration.as_secs().div_ceil(60))
+            }
+            ModAction::Followers(Setting::Off) => "/followersoff".to_string(),
+            ModAction::EmoteOnly(on) => switch("emoteonly", *on),
+            ModAction::Subscribers(on) => switch("subscribers", *on),
+            ModAction::Clear => "/clear".to_string(),
+        }
+    }
+}
+
+/// Send `action` to the channel on screen. Twitch answers with a notice
+/// or the `CLEARCHAT` for it, which show up in the channel like any other.
+pub async fn moderate(
+    action: ModAction,
+    username: &str,
+    channels: Arc<RwLock<Channels>>,
+    client: &Client,
+) {
+    let channel = {
+        let mut channels = channels.write().await;
+        if username.is_empty() {
+            channels.notify(
+                "You are not logged in, enter :login <username> to moderate."
+                    .red()
+                    .to_string(),
+            );
+            return;
+        }
+        match channels.active() {
+            Some(channel) => channel.name.clone(),
+            None => {
+                channels.notify("Join a channel to moderate it.".red().to_string());
+                return;
+            }
+        }
+    };
+    // The lock isn't held while sending waits for the connection.
+    let sent = client.privmsg(channel, action.chat_command()).await;
+    if let Err(error) = sent {
+        let notice = format!("Couldn't send that: {}", error).red().to_string();
+        channels.write().await.notify(notice);
+    }
+}
+
+/// A duration like `10m`, `1h30m` or `2d`, in weeks, days, hours, minutes
+/// and seconds, or a plain number of seconds.
+pub fn parse_duration(text: &str) -> Option<Duration> {
+    if let Ok(seconds) = text.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let mut seconds: u64 = 0;
+    let mut number = String::new();
+    for c in text.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let unit = match c.to_ascii_lowercase() {
+            'w' => 7 * 24 * 60 * 60,
+            'd' => 24 * 60 * 60,
+            'h' => 60 * 60,
+            'm' => 60,
+            's' => 1,
+            _ => return None,
+        };
+        let amount: u64 = number.parse().ok()?;
+        seconds = seconds.checked_add(amount.checked_mul(unit)?)?;
+        number.clear();
+    }
+    // A number without a unit, like the 30 in 1h30, could mean anything.
+    if !number.is_empty() || text.is_empty() {
+        return None;
+    }
+    Some(Duration::from_secs(seconds))
+}
+
+/// `duration` the way `parse_duration` reads it, e.g. `1h30m`.
+pub fn format_duration(duration: Duration) -> String {
+    let mut seconds = duration.as_secs();
+    if seconds == 0 {
+        return "0s".to_string();
+    }
+    let mut formatted = String::new();
+    for (unit, length) in [("w", 604_800), ("d", 86_400), ("h", 3600), ("m", 60), ("s", 1)] {
+        if seconds >= length {
+            formatted.push_str(&format!("{}{}", seconds / length, unit));
+            seconds %= length;
+        }
+    }
+    formatted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("600"), Some(Duration::from_secs(600)));
+        assert_eq!(parse_duration("10m"), Some(Duration::from_secs(600)));
+        assert_eq!(parse_duration("1h30m"), Some(Duration::from_secs(5400)));
+        assert_eq!(parse_duration("1d12H"), Some(Duration::from_secs(129_600)));
+        assert_eq!(parse_duration("2w"), Some(Duration::from_secs(1_209_600)));
+    }
+
+    #[test]
+    fn formats_durations() {
+        assert_eq!(format_duration(Duration::from_secs(0)), "0s");
+        assert_eq!(format_duration(Duration::from_secs(30)), "30s");
+        assert_eq!(format_duration(Duration::from_secs(5400)), "1h30m");
+        assert_eq!(format_duration(Duration::from_secs(604_861)), "1w1m1s");
+    }
+
+    #[test]
+    fn rejects_bad_durations() {
+        assert_eq!(parse_duration(""), None);
+        assert_eq!(parse_duration("m"), None);
+        assert_eq!(parse_duration("10x"), None);
+        assert_eq!(parse_duration("1h30"), None);
+        assert_eq!(parse_duration("-5m"), None);
+    }
+
+    #[test]
+    fn parses_settings() {
+        assert_eq!(Setting::parse("Off"), Some(Setting::Off));
+        assert_eq!(Setting::parse("on"), Some(Setting::On));
+        assert_eq!(
+            Setting::parse("30s"),
+            Some(Setting::For(Duration::from_secs(30)))
+        );
+        assert_eq!(Setting::parse("later"), None);
+    }
+
+    #[test]
+    fn followers_round_up_to_minutes() {
+        let followers = |seconds| ModAction::Followers(Setting::For(Duration::from_secs(seconds)));
+        assert_eq!(followers(30).chat_command(), "/followers 1m");
+        assert_eq!(followers(600).chat_command(), "/followers 10m");
+        assert_eq!(followers(601).chat_command(), "/followers 11m");
+        assert_eq!(followers(0).chat_command(), "/followers 0m");
+    }
+}
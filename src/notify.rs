@@ -0,0 +1,121 @@
+use crate::user_config::Notifications;
+use std::{io, process::Stdio};
+use tokio::process::{Child, Command};
+
+/// A highlighted message, as it's handed to the notifiers.
+pub struct Notification {
+    pub channel: String,
+    pub user: String,
+    pub message: String,
+}
+
+/// Somewhere highlighted messages are sent, besides the mentions view and
+/// the terminal bell.
+pub trait Notifier: Send + Sync {
+    fn notify(&self, notification: &Notification) -> io::Result<()>;
+}
+
+/// Runs a command for every highlighted message, `{channel}`, `{user}` and
+/// `{message}` in its arguments are replaced with the message's.
+pub struct CommandNotifier {
+    command: Vec<String>,
+}
+
+impl CommandNotifier {
+    pub fn new(command: Vec<String>) -> CommandNotifier {
+        CommandNotifier { command }
+    }
+
+    /// A desktop notification, sent with `notify-send`.
+    pub fn desktop() -> CommandNotifier {
+        CommandNotifier::new(
+            ["notify-send", "--app-name=tuitch", "{user} in #{channel}", "{message}"]
+                .iter()
+                .map(|arg| arg.to_string())
+                .collect(),
+        )
+    }
+
+    /// Start the command for `notification`, without waiting for it.
+    pub fn run(&self, notification: &Notification) -> io::Result<Child> {
+        let args: Vec<String> = self
+            .command
+            .iter()
+            .map(|arg| {
+                arg.replace("{channel}", &notification.channel)
+                    .replace("{user}", &notification.user)
+                    .replace("{message}", &notification.message)
+            })
+            .collect();
+        let (program, args) = args
+            .split_first()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "empty command"))?;
+        // Whatever the command prints would end up all over the screen.
+        Command::new(program)
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+    }
+}
+
+impl Notifier for CommandNotifier {
+    fn notify(&self, notification: &Notification) -> io::Result<()> {
+        let mut child = self.run(notification)?;
+        // Waited on in the background so finished commands don't linger.
+        tokio::spawn(async move { child.wait().await });
+        Ok(())
+    }
+}
+
+/// The notifiers turned on in the `[notifications]` table.
+pub fn notifiers(config: &Notifications) -> Vec<Box<dyn Notifier>> {
+    let mut notifiers: Vec<Box<dyn Notifier>> = Vec::new();
+    if config.desktop {
+        notifiers.push(Box::new(CommandNotifier::desktop()));
+    }
+    if !config.command.is_empty() {
+        notifiers.push(Box::new(CommandNotifier::new(config.command.clone())));
+    }
+    notifiers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn notification() -> Notification {
+        Notification {
+            channel: "xqc".to_string(),
+            user: "bob".to_string(),
+            message: "hi tuitch, it's {user}".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn command_gets_the_message() {
+        let path = std::env::temp_dir().join(format!("tuitch-notify-{}", std::process::id()));
+        let notifier = CommandNotifier::new(vec![
+            "sh".to_string(),
+            "-c".to_string(),
+            "printf '%s|%s' \"$1\" \"$2\" > \"$0\"".to_string(),
+            path.to_string_lossy().into_owned(),
+            "{user} in #{channel}".to_string(),
+            "{message}".to_string(),
+        ]);
+        let status = notifier.run(&notification()).unwrap().wait().await.unwrap();
+        assert!(status.success());
+        let written = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).ok();
+        // Placeholders in the message itself are left alone.
+        assert_eq!(written, "bob in #xqc|hi tuitch, it's {user}");
+    }
+
+    #[tokio::test]
+    async fn empty_command_is_an_error() {
+        let notifier = CommandNotifier::new(Vec::new());
+        assert!(notifier.run(&notification()).is_err());
+    }
+}
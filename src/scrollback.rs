@@ -0,0 +1,303 @@
+use std::collections::VecDeque;
+
+/// The number of messages kept per channel when the config doesn't say.
+pub const DEFAULT_HISTORY_SIZE: usize = 1000;
+
+/// A formatted line of chat and the message it came from, so it can be
+/// found again when the message is deleted.
+#[derive(Clone)]
+pub struct Line {
+    /// Everything in front of the message text, like the time and name.
+    pub prefix: String,
+    pub body: String,
+    /// Twitch's id of the chat message.
+    pub message_id: Option<String>,
+    /// The login of whoever sent it.
+    pub sender: Option<String>,
+    /// The message text as it was sent.
+    pub original: Option<String>,
+    /// Set once the message is deleted, or its sender timed out or banned.
+    pub redacted: bool,
+    /// The message this one replies to, shown on a row of its own above it.
+    pub quote: Option<String>,
+    /// On the line standing in for ignored messages, how many there were.
+    pub hidden: usize,
+    /// On a copy in the mentions view, the channel it came from.
+    pub channel: Option<String>,
+}
+
+impl Line {
+    pub fn text(&self) -> String {
+        format!("{}{}", self.prefix, self.body)
+    }
+}
+
+/// Lines from tuitch itself and server notices, which are never redacted.
+impl From<String> for Line {
+    fn from(body: String) -> Line {
+        Line {
+            prefix: String::new(),
+            body,
+            message_id: None,
+            sender: None,
+            original: None,
+            redacted: false,
+            quote: None,
+            hidden: 0,
+            channel: None,
+        }
+    }
+}
+
+/// A channel's formatted lines, bounded to `capacity` entries with the
+/// oldest dropped first, and how far the user has scrolled up through them.
+pub struct Scrollback {
+    lines: VecDeque<Line>,
+    capacity: usize,
+    // Number of messages between the bottom of the view and the newest
+    // message, zero while following the chat.
+    offset: usize,
+    // Messages that arrived while scrolled up.
+    unseen: usize,
+    // The id of the message picked to reply to.
+    selected: Option<String>,
+}
+
+impl Scrollback {
+    pub fn new(capacity: usize) -> Scrollback {
+        let capacity = capacity.max(1);
+        Scrollback {
+            lines: VecDeque::with_capacity(capacity),
+            capacity,
+            offset: 0,
+            unseen: 0,
+            selected: None,
+        }
+    }
+
+    /// Add a message, the view stays on the same messages while scrolled up.
+    pub fn push(&mut self, line: impl Into<Line>) {
+        let line = line.into();
+        if self.lines.len() == self.capacity {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(line);
+        if self.offset > 0 {
+            self.offset = (self.offset + 1).min(self.lines.len() - 1);
+            self.unseen += 1;
+        }
+    }
+
+    pub fn scroll_up(&mut self, amount: usize) {
+        self.offset = (self.offset + amount).min(self.lines.len().saturating_sub(1));
+    }
+
+    /// Scroll towards the newest message, following the chat again
+    /// once the bottom is reached.
+    pub fn scroll_down(&mut self, amount: usize) {
+        self.offset = self.offset.saturating_sub(amount);
+        if self.offset == 0 {
+            self.unseen = 0;
+        }
+    }
+
+    pub fn scroll_to_top(&mut self) {
+        self.scroll_up(self.lines.len());
+    }
+
+    pub fn scroll_to_bottom(&mut self) {
+        self.scroll_down(self.offset);
+    }
+
+    pub fn is_following(&self) -> bool {
+        self.offset == 0
+    }
+
+    pub fn unseen(&self) -> usize {
+        self.unseen
+    }
+
+    /// The last `height` messages above the current scroll position.
+    pub fn visible(&self, height: usize) -> impl Iterator<Item = &Line> {
+        let end = self.lines.len() - self.offset;
+        let start = end.saturating_sub(height);
+        self.lines.range(start..end)
+    }
+
+    /// Every line kept, oldest first.
+    pub fn lines_mut(&mut self) -> impl Iterator<Item = &mut Line> {
+        self.lines.iter_mut()
+    }
+
+    pub fn last_mut(&mut self) -> Option<&mut Line> {
+        self.lines.back_mut()
+    }
+
+    /// The newest message from `login` still kept.
+    pub fn last_from(&self, login: &str) -> Option<&Line> {
+        self.lines
+            .iter()
+            .rev()
+            .find(|line| line.message_id.is_some() && line.sender.as_deref() == Some(login))
+    }
+
+    pub fn find(&self, message_id: &str) -> Option<&Line> {
+        self.lines
+            .iter()
+            .find(|line| line.message_id.as_deref() == Some(message_id))
+    }
+
+    pub fn selected(&self) -> Option<&str> {
+        self.selected.as_deref()
+    }
+
+    /// Pick the message before the picked one, or the newest one in view
+    /// to start with. Only chat messages can be picked.
+    pub fn select_older(&mut self, height: usize) {
+        let end = self
+            .selected_index()
+            .unwrap_or(self.lines.len() - self.offset);
+        if let Some(index) = (0..end).rev().find(|&index| self.lines[index].message_id.is_some()) {
+            self.select(index, height);
+        }
+    }
+
+    pub fn select_newer(&mut self, height: usize) {
+        let start = match self.selected_index() {
+            Some(index) => index + 1,
+            None => return,
+        };
+        if let Some(index) = (start..self.lines.len()).find(|&index| self.lines[index].message_id.is_some()) {
+            self.select(index, height);
+        }
+    }
+
+    /// Stop picking a message, returns the id of the picked one.
+    pub fn take_selection(&mut self) -> Option<String> {
+        self.selected.take()
+    }
+
+    fn selected_index(&self) -> Option<usize> {
+        let selected = self.selected.as_deref()?;
+        self.lines
+            .iter()
+            .position(|line| line.message_id.as_deref() == Some(selected))
+    }
+
+    /// Pick the message at `index` and scroll it into a view of `height` messages.
+    fn select(&mut self, index: usize, height: usize) {
+        self.selected = self.lines[index].message_id.clone();
+        let end = self.lines.len() - self.offset;
+        if index >= end {
+            self.scroll_down(index + 1 - end);
+        } else if index < end.saturating_sub(height) {
+            self.scroll_up(end - height - index);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(id: &str) -> Line {
+        Line {
+            message_id: Some(id.to_string()),
+            ..Line::from(id.to_string())
+        }
+    }
+
+    fn filled(capacity: usize, bodies: &[&str]) -> Scrollback {
+        let mut scrollback = Scrollback::new(capacity);
+        for body in bodies {
+            scrollback.push(message(body));
+        }
+        scrollback
+    }
+
+    fn visible(scrollback: &Scrollback, height: usize) -> Vec<&str> {
+        scrollback
+            .visible(height)
+            .map(|line| line.body.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn drops_the_oldest_at_capacity() {
+        let scrollback = filled(3, &["a", "b", "c", "d"]);
+        assert_eq!(visible(&scrollback, 10), ["b", "c", "d"]);
+        let scrollback = filled(0, &["a", "b"]);
+        assert_eq!(visible(&scrollback, 10), ["b"]);
+    }
+
+    #[test]
+    fn keeps_the_view_while_scrolled_up() {
+        let mut scrollback = filled(10, &["1", "2", "3", "4", "5"]);
+        scrollback.scroll_up(2);
+        assert_eq!(visible(&scrollback, 2), ["2", "3"]);
+        scrollback.push(message("6"));
+        scrollback.push(message("7"));
+        assert_eq!(visible(&scrollback, 2), ["2", "3"]);
+        assert_eq!(scrollback.unseen(), 2);
+        assert!(!scrollback.is_following());
+
+        scrollback.scroll_down(1);
+        assert_eq!(scrollback.unseen(), 2);
+        scrollback.scroll_down(3);
+        assert!(scrollback.is_following());
+        assert_eq!(scrollback.unseen(), 0);
+
+        scrollback.scroll_up(1);
+        scrollback.push(message("8"));
+        assert_eq!(scrollback.unseen(), 1);
+        scrollback.scroll_to_bottom();
+        assert_eq!(scrollback.unseen(), 0);
+        assert_eq!(visible(&scrollback, 2), ["7", "8"]);
+    }
+
+    #[test]
+    fn visible_at_either_end() {
+        let mut scrollback = filled(10, &["1", "2", "3", "4"]);
+        assert_eq!(visible(&scrollback, 2), ["3", "4"]);
+        assert_eq!(visible(&scrollback, 10), ["1", "2", "3", "4"]);
+        scrollback.scroll_up(100);
+        assert_eq!(visible(&scrollback, 2), ["1"]);
+        scrollback.scroll_to_bottom();
+        scrollback.scroll_to_top();
+        assert_eq!(visible(&scrollback, 2), ["1"]);
+        assert!(Scrollback::new(5).visible(2).next().is_none());
+    }
+
+    #[test]
+    fn selects_only_chat_messages() {
+        let mut scrollback = Scrollback::new(10);
+        for (index, body) in ["m1", "notice", "m2", "notice", "m3"].iter().enumerate() {
+            if index % 2 == 0 {
+                scrollback.push(message(body));
+            } else {
+                scrollback.push(body.to_string());
+            }
+        }
+        scrollback.select_newer(2);
+        assert_eq!(scrollback.selected(), None);
+        scrollback.select_older(2);
+        assert_eq!(scrollback.selected(), Some("m3"));
+        scrollback.select_older(2);
+        assert_eq!(scrollback.selected(), Some("m2"));
+        scrollback.select_older(2);
+        assert_eq!(scrollback.selected(), Some("m1"));
+        assert_eq!(visible(&scrollback, 2), ["m1", "notice"]);
+        scrollback.select_older(2);
+        assert_eq!(scrollback.selected(), Some("m1"));
+
+        scrollback.select_newer(2);
+        assert_eq!(scrollback.selected(), Some("m2"));
+        assert_eq!(visible(&scrollback, 2), ["notice", "m2"]);
+        scrollback.select_newer(2);
+        scrollback.select_newer(2);
+        assert_eq!(scrollback.selected(), Some("m3"));
+        assert!(scrollback.is_following());
+        assert_eq!(scrollback.take_selection().as_deref(), Some("m3"));
+        assert_eq!(scrollback.selected(), None);
+    }
+}
@@ -0,0 +1,734 @@
+use crate::user_config::ConfigError;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+    collections::BTreeMap,
+    env, fmt, fs,
+    path::{Path, PathBuf},
+};
+
+/// The themes that come with tuitch, the first is used when the config doesn't name one.
+pub const BUILT_IN_THEMES: &[&str] = &["dark", "light", "high-contrast"];
+
+/// A terminal colour, as a theme writes it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Color {
+    /// One of the 16 colours of the terminal's palette, 8 and up are the bright ones.
+    Ansi(u8),
+    /// One of the 256 xterm colours.
+    Fixed(u8),
+    Rgb(u8, u8, u8),
+}
+
+const COLOR_NAMES: &[&str] = &[
+    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+];
+
+/// The 16 colours as xterm shows them, terminals with another palette
+/// are close enough for picking the nearest one.
+const PALETTE_16: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+/// The levels of each channel in the xterm 6x6x6 colour cube.
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+/// How many colours the terminal shows.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ColorSupport {
+    #[default]
+    TrueColor,
+    Palette256,
+    Palette16,
+}
+
+impl ColorSupport {
+    /// What the terminal says it supports through `COLORTERM` and `TERM`.
+    pub fn detect() -> ColorSupport {
+        let colorterm = env::var("COLORTERM").unwrap_or_default();
+        if colorterm == "truecolor" || colorterm == "24bit" {
+            ColorSupport::TrueColor
+        } else if env::var("TERM").unwrap_or_default().contains("256color") {
+            ColorSupport::Palette256
+        } else {
+            ColorSupport::Palette16
+        }
+    }
+}
+
+impl Color {
+    fn parse(name: &str) -> Option<Color> {
+        if let Some(hex) = name.strip_prefix('#') {
+            let channel = |range| u8::from_str_radix(hex.get(range)?, 16).ok();
+            if hex.len() != 6 {
+                return None;
+            }
+            return Some(Color::Rgb(channel(0..2)?, channel(2..4)?, channel(4..6)?));
+        }
+        if let Ok(number) = name.parse() {
+            return Some(Color::Fixed(number));
+        }
+        let (bright, name) = match name.strip_prefix("bright_") {
+            Some(name) => (8, name),
+            None if name == "gray" || name == "grey" => (8, "black"),
+            None => (0, name),
+        };
+        let index = COLOR_NAMES.iter().position(|color| *color == name)?;
+        Some(Color::Ansi(bright + index as u8))
+    }
+
+    pub fn rgb(&self) -> (u8, u8, u8) {
+        match *self {
+            Color::Ansi(index) => PALETTE_16[index as usize % 16],
+            Color::Fixed(index) if index < 16 => PALETTE_16[index as usize],
+            Color::Fixed(index) if index < 232 => {
+                let index = (index - 16) as usize;
+                (
+                    CUBE_LEVELS[index / 36],
+                    CUBE_LEVELS[index / 6 % 6],
+                    CUBE_LEVELS[index % 6],
+                )
+            }
+            Color::Fixed(index) => {
+                let level = 8 + (index - 232) * 10;
+                (level, level, level)
+            }
+            Color::Rgb(r, g, b) => (r, g, b),
+        }
+    }
+
+    /// The nearest colour the terminal can show.
+    pub fn downgrade(self, support: ColorSupport) -> Color {
+        match (support, self) {
+            (ColorSupport::TrueColor, color) | (_, color @ Color::Ansi(_)) => color,
+            (ColorSupport::Palette256, Color::Fixed(_)) => self,
+            (ColorSupport::Palette256, Color::Rgb(r, g, b)) => {
+                let nearest_level = |value: u8| {
+                    (0..6)
+                        .min_by_key(|&level| CUBE_LEVELS[level].abs_diff(value))
+                        .unwrap()
+                };
+                let cube = 16 + 36 * nearest_level(r) + 6 * nearest_level(g) + nearest_level(b);
+                let average = (r as usize + g as usize + b as usize) / 3;
+                let gray = 232 + (average.saturating_sub(3) / 10).min(23);
+                let (cube, gray) = (Color::Fixed(cube as u8), Color::Fixed(gray as u8));
+                if distance(gray.rgb(), (r, g, b)) < distance(cube.rgb(), (r, g, b)) {
+                    gray
+                } else {
+                    cube
+                }
+            }
+            (ColorSupport::Palette16, color) => {
+                let index = (0..16)
+                    .min_by_key(|&index| distance(PALETTE_16[index], color.rgb()))
+                    .unwrap();
+                Color::Ansi(index as u8)
+            }
+        }
+    }
+
+    /// The SGR parameters for this colour, `background` picks the 40s over the 30s.
+    fn sgr(&self, background: bool) -> String {
+        let base = if background { 40 } else { 30 };
+        match *self {
+            Color::Ansi(index) if index < 8 => (base + index as u16).to_string(),
+            Color::Ansi(index) => (base + 60 + (index - 8) as u16).to_string(),
+            Color::Fixed(index) => format!("{};5;{}", base + 8, index),
+            Color::Rgb(r, g, b) => format!("{};2;{};{};{}", base + 8, r, g, b),
+        }
+    }
+}
+
+/// How a theme slot looks, written like `bold yellow`, `dimmed`,
+/// `#ff8800 on black`, `208` or `none`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Style {
+    pub fg: Option<Color>,
+    pub bg: Option<Color>,
+    pub bold: bool,
+    pub dimmed: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub reversed: bool,
+    pub strikethrough: bool,
+}
+
+impl Style {
+    pub fn parse(spec: &str) -> Result<Style, String> {
+        let mut style = Style::default();
+        let mut words = spec.split_whitespace().map(str::to_lowercase);
+        while let Some(word) = words.next() {
+            match word.as_str() {
+                "none" | "default" => {}
+                "bold" => style.bold = true,
+                "dim" | "dimmed" => style.dimmed = true,
+                "italic" => style.italic = true,
+                "underline" => style.underline = true,
+                "reverse" | "reversed" => style.reversed = true,
+                "strikethrough" => style.strikethrough = true,
+                "on" => {
+                    let color = words.next().ok_or("expected a colour after `on`")?;
+                    style.bg = Some(Color::parse(&color).ok_or_else(|| unknown(&color))?);
+                }
+                color => style.fg = Some(Color::parse(color).ok_or_else(|| unknown(color))?),
+            }
+        }
+        Ok(style)
+    }
+
+    /// This style in colours the terminal can show.
+    pub fn downgrade(&self, support: ColorSupport) -> Style {
+        Style {
+            fg: self.fg.map(|color| color.downgrade(support)),
+            bg: self.bg.map(|color| color.downgrade(support)),
+            ..self.clone()
+        }
+    }
+
+    /// `text` in this style, the style ends with it.
+    pub fn paint<T: fmt::Display>(&self, text: T) -> String {
+        let mut codes = Vec::new();
+        for (set, code) in [
+            (self.bold, "1"),
+            (self.dimmed, "2"),
+            (self.italic, "3"),
+            (self.underline, "4"),
+            (self.reversed, "7"),
+            (self.strikethrough, "9"),
+        ] {
+            if set {
+                codes.push(code.to_string());
+            }
+        }
+        codes.extend(self.fg.map(|color| color.sgr(false)));
+        codes.extend(self.bg.map(|color| color.sgr(true)));
+        if codes.is_empty() {
+            return text.to_string();
+        }
+        format!("\x1b[{}m{}\x1b[0m", codes.join(";"), text)
+    }
+
+    fn spec(&self) -> String {
+        let mut words = Vec::new();
+        for (set, word) in [
+            (self.bold, "bold"),
+            (self.dimmed, "dimmed"),
+            (self.italic, "italic"),
+            (self.underline, "underline"),
+            (self.reversed, "reversed"),
+            (self.strikethrough, "strikethrough"),
+        ] {
+            if set {
+                words.push(word.to_string());
+            }
+        }
+        words.extend(self.fg.map(|color| color_spec(&color)));
+        if let Some(color) = self.bg {
+            words.push(format!("on {}", color_spec(&color)));
+        }
+        if words.is_empty() {
+            return "none".to_string();
+        }
+        words.join(" ")
+    }
+}
+
+fn color_spec(color: &Color) -> String {
+    match *color {
+        Color::Ansi(index) if index < 8 => COLOR_NAMES[index as usize].to_string(),
+        Color::Ansi(index) => format!("bright_{}", COLOR_NAMES[(index - 8) as usize]),
+        Color::Fixed(index) => index.to_string(),
+        Color::Rgb(r, g, b) => format!("#{:02x}{:02x}{:02x}", r, g, b),
+    }
+}
+
+/// How far apart two colours are, squared.
+fn distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> u32 {
+    let channel = |a: u8, b: u8| (a.abs_diff(b) as u32).pow(2);
+    channel(a.0, b.0) + channel(a.1, b.1) + channel(a.2, b.2)
+}
+
+/// The relative luminance of a colour, from 0 for black to 1 for white.
+fn luminance((r, g, b): (u8, u8, u8)) -> f64 {
+    let linear = |value: u8| {
+        let value = value as f64 / 255.0;
+        if value <= 0.03928 {
+            value / 12.92
+        } else {
+            ((value + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    0.2126 * linear(r) + 0.7152 * linear(g) + 0.0722 * linear(b)
+}
+
+/// The WCAG contrast ratio of two colours, from 1 for the same colour to 21
+/// for black on white.
+pub fn contrast(a: (u8, u8, u8), b: (u8, u8, u8)) -> f64 {
+    let (a, b) = (luminance(a), luminance(b));
+    (a.max(b) + 0.05) / (a.min(b) + 0.05)
+}
+
+fn rgb_to_hsl((r, g, b): (u8, u8, u8)) -> (f64, f64, f64) {
+    let (r, g, b) = (r as f64 / 255.0, g as f64 / 255.0, b as f64 / 255.0);
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let lightness = (max + min) / 2.0;
+    if max == min {
+        return (0.0, 0.0, lightness);
+    }
+    let delta = max - min;
+    let saturation = if lightness > 0.5 {
+        delta / (2.0 - max - min)
+    } else {
+        delta / (max + min)
+    };
+    let hue = if max == r {
+        (g - b) / delta + if g < b { 6.0 } else { 0.0 }
+    } else if max == g {
+        (b - r) / delta + 2.0
+    } else {
+        (r - g) / delta + 4.0
+    };
+    (hue * 60.0, saturation, lightness)
+}
+
+fn hsl_to_rgb(hue: f64, saturation: f64, lightness: f64) -> (u8, u8, u8) {
+    let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+    let x = chroma * (1.0 - ((hue / 60.0) % 2.0 - 1.0).abs());
+    let (r, g, b) = match hue as u32 / 60 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let m = lightness - chroma / 2.0;
+    let channel = |value: f64| ((value + m) * 255.0).round().clamp(0.0, 255.0) as u8;
+    (channel(r), channel(g), channel(b))
+}
+
+/// `color` made lighter or darker, keeping its hue, until it stands out
+/// from `background` by at least `min_contrast`. Contrast is checked on the
+/// colour the terminal shows, which with fewer colours can be another one.
+pub fn readable(
+    color: (u8, u8, u8),
+    background: (u8, u8, u8),
+    min_contrast: f64,
+    support: ColorSupport,
+) -> Color {
+    let (hue, saturation, mut lightness) = rgb_to_hsl(color);
+    // Below this, white text stands out more than black.
+    let step = if luminance(background) < 0.18 { 0.02 } else { -0.02 };
+    let shown = |(r, g, b): (u8, u8, u8)| Color::Rgb(r, g, b).downgrade(support);
+    let mut color = shown(color);
+    while contrast(color.rgb(), background) < min_contrast
+        && (0.0..=1.0).contains(&(lightness + step))
+    {
+        lightness += step;
+        color = shown(hsl_to_rgb(hue, saturation, lightness));
+    }
+    color
+}
+
+/// A colour for a chatter who hasn't picked one, the same one every time.
+pub fn hashed_color(login: &str) -> (u8, u8, u8) {
+    // FNV-1a, which unlike the standard hasher is the same on every build.
+    let hash = login.bytes().fold(0x811c_9dc5_u32, |hash, byte| {
+        (hash ^ byte as u32).wrapping_mul(0x0100_0193)
+    });
+    hsl_to_rgb((hash % 360) as f64, 0.7, 0.55)
+}
+
+fn unknown(word: &str) -> String {
+    format!(
+        "unknown colour or style `{}`, expected e.g. `bold`, `red`, `bright_blue`, `#ff8800` or `208`",
+        word
+    )
+}
+
+impl Serialize for Color {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&color_spec(self))
+    }
+}
+
+impl<'de> Deserialize<'de> for Color {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Color, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        Color::parse(&name.to_lowercase()).ok_or_else(|| de::Error::custom(unknown(&name)))
+    }
+}
+
+impl Serialize for Style {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.spec())
+    }
+}
+
+impl<'de> Deserialize<'de> for Style {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Style, D::Error> {
+        let spec = String::deserialize(deserializer)?;
+        Style::parse(&spec).map_err(de::Error::custom)
+    }
+}
+
+/// The colours of everything tuitch draws, one style per slot. Slots a
+/// theme leaves out are taken from the dark theme.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Theme {
+    /// The terminal's background, names are kept readable against it.
+    pub background: Color,
+    /// How far names have to stand out from the background, as a WCAG
+    /// contrast ratio from 1 to 21.
+    pub min_contrast: f64,
+    /// The time in front of every message.
+    pub timestamp: Style,
+    /// Chatters' names. Their colour is the one they picked on Twitch, or
+    /// one picked for them unless this slot sets one.
+    pub name: Style,
+    /// Messages from Twitch and tuitch itself, like joining a chat.
+    pub notice: Style,
+    /// Subs, gifted subs, raids and other events.
+    pub sub_event: Style,
+    /// Bans, timeouts and cleared chats.
+    pub ban: Style,
+    /// Deleted messages, what's left of them.
+    pub deleted: Style,
+    /// Messages with one of the highlight words.
+    pub highlight: Style,
+    /// The message a reply is to, above the reply.
+    pub reply: Style,
+    /// Emotes in chat messages, when they aren't drawn as pictures.
+    pub emote: Style,
+    /// Your own name on the messages you send.
+    pub own_message: Style,
+    /// The prompt in front of the input line.
+    pub prompt: Style,
+    /// The hint shown while the input line is empty.
+    pub placeholder: Style,
+    /// The status bar, and the notice while scrolled up.
+    pub status_bar: Style,
+    /// The tab on screen in the tab bar.
+    pub active_tab: Style,
+    /// Set when the theme is switched to, colours are brought down to it.
+    #[serde(skip)]
+    pub color_support: ColorSupport,
+}
+
+impl Default for Theme {
+    fn default() -> Theme {
+        Theme::built_in("dark").unwrap()
+    }
+}
+
+impl Theme {
+    pub fn built_in(name: &str) -> Option<Theme> {
+        let style = |spec: &str| Style::parse(spec).unwrap();
+        let theme = match name {
+            "dark" => Theme {
+                background: Color::Ansi(0),
+                min_contrast: 4.5,
+                timestamp: style("dimmed"),
+                name: style("bold"),
+                notice: style("none"),
+                sub_event: style("magenta"),
+                ban: style("red"),
+                deleted: style("dimmed"),
+                highlight: style("bold yellow"),
+                reply: style("dimmed italic"),
+                emote: style("italic cyan"),
+                own_message: style("bold"),
+                prompt: style("bold"),
+                placeholder: style("dimmed"),
+                status_bar: style("reversed"),
+                active_tab: style("reversed"),
+                color_support: ColorSupport::default(),
+            },
+            "light" => Theme {
+                background: Color::Ansi(15),
+                min_contrast: 4.5,
+                timestamp: style("bright_black"),
+                name: style("bold"),
+                notice: style("none"),
+                sub_event: style("magenta"),
+                ban: style("red"),
+                deleted: style("bright_black"),
+                highlight: style("bold blue"),
+                reply: style("italic bright_black"),
+                emote: style("italic cyan"),
+                own_message: style("bold"),
+                prompt: style("bold blue"),
+                placeholder: style("bright_black"),
+                status_bar: style("white on blue"),
+                active_tab: style("white on blue"),
+                color_support: ColorSupport::default(),
+            },
+            "high-contrast" => Theme {
+                background: Color::Ansi(0),
+                min_contrast: 7.0,
+                timestamp: style("bright_white"),
+                name: style("bold"),
+                notice: style("bold bright_white"),
+                sub_event: style("bold bright_magenta"),
+                ban: style("bold bright_red"),
+                deleted: style("italic bright_white"),
+                highlight: style("bold black on bright_yellow"),
+                reply: style("italic bright_white"),
+                emote: style("underline bright_green"),
+                own_message: style("bold bright_cyan"),
+                prompt: style("bold bright_white"),
+                placeholder: style("bright_white"),
+                status_bar: style("bold black on bright_white"),
+                active_tab: style("bold black on bright_white"),
+                color_support: ColorSupport::default(),
+            },
+            _ => return None,
+        };
+        Some(theme)
+    }
+
+    /// The theme with every colour brought down to what the terminal shows.
+    fn downgrade(mut self, support: ColorSupport) -> Theme {
+        for style in [
+            &mut self.timestamp,
+            &mut self.name,
+            &mut self.notice,
+            &mut self.sub_event,
+            &mut self.ban,
+            &mut self.deleted,
+            &mut self.highlight,
+            &mut self.reply,
+            &mut self.emote,
+            &mut self.own_message,
+            &mut self.prompt,
+            &mut self.placeholder,
+            &mut self.status_bar,
+            &mut self.active_tab,
+        ] {
+            *style = style.downgrade(support);
+        }
+        self.color_support = support;
+        self
+    }
+
+    /// How `login`'s name looks, in the colour they `picked` on Twitch
+    /// made readable against the background.
+    pub fn name_style(&self, login: &str, picked: Option<(u8, u8, u8)>) -> Style {
+        let mut style = self.name.clone();
+        let color = match (picked, self.name.fg) {
+            (Some(color), _) => color,
+            (None, Some(_)) => return style,
+            (None, None) => hashed_color(login),
+        };
+        style.fg = Some(readable(
+            color,
+            self.background.rgb(),
+            self.min_contrast,
+            self.color_support,
+        ));
+        style
+    }
+}
+
+/// Why a theme couldn't be switched to.
+#[derive(Debug)]
+pub enum ThemeError {
+    Unknown { name: String, names: Vec<String> },
+    Invalid(ConfigError),
+}
+
+impl fmt::Display for ThemeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ThemeError::Unknown { name, names } => write!(
+                f,
+                "There is no theme {}, the themes are: {}",
+                name,
+                names.join(", ")
+            ),
+            ThemeError::Invalid(error) => error.fmt(f),
+        }
+    }
+}
+
+/// The theme in use and where the others are found: the `[themes.<name>]`
+/// tables of the config, `<name>.toml` files in the themes directory next
+/// to it, then the built-in ones.
+pub struct Themes {
+    name: String,
+    current: Theme,
+    custom: BTreeMap<String, Theme>,
+    dir: PathBuf,
+    color_support: ColorSupport,
+}
+
+impl Themes {
+    pub fn new(custom: BTreeMap<String, Theme>, config_path: &Path) -> Themes {
+        let color_support = ColorSupport::detect();
+        Themes {
+            name: BUILT_IN_THEMES[0].to_string(),
+            current: Theme::default().downgrade(color_support),
+            custom,
+            dir: config_path.with_file_name("themes"),
+            color_support,
+        }
+    }
+
+    pub fn current(&self) -> &Theme {
+        &self.current
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Use the theme called `name` from now on, files in the themes
+    /// directory are read again every time.
+    pub fn switch(&mut self, name: &str) -> Result<(), ThemeError> {
+        // Configs from before themes had names say "default".
+        let name = match name {
+            "default" => BUILT_IN_THEMES[0],
+            name => name,
+        };
+        self.current = self.load(name)?.downgrade(self.color_support);
+        self.name = name.to_string();
+        Ok(())
+    }
+
+    fn load(&self, name: &str) -> Result<Theme, ThemeError> {
+        if let Some(theme) = self.custom.get(name) {
+            return Ok(theme.clone());
+        }
+        let path = self.dir.join(format!("{}.toml", name));
+        match fs::read_to_string(&path) {
+            Ok(contents) => {
+                return toml::from_str(&contents)
+                    .map_err(|error| ThemeError::Invalid(ConfigError::parse(&path, error)))
+            }
+            Err(error) if error.kind() != std::io::ErrorKind::NotFound => {
+                return Err(ThemeError::Invalid(ConfigError::Read {
+                    path,
+                    source: error,
+                }))
+            }
+            Err(_) => {}
+        }
+        Theme::built_in(name).ok_or_else(|| ThemeError::Unknown {
+            name: name.to_string(),
+            names: self.names(),
+        })
+    }
+
+    /// Every theme there is to switch to, sorted.
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = BUILT_IN_THEMES.iter().map(|name| name.to_string()).collect();
+        names.extend(self.custom.keys().cloned());
+        if let Ok(entries) = fs::read_dir(&self.dir) {
+            for path in entries.flatten().map(|entry| entry.path()) {
+                if path.extension().is_some_and(|extension| extension == "toml") {
+                    if let Some(stem) = path.file_stem().and_then(|stem| stem.to_str()) {
+                        names.push(stem.to_string());
+                    }
+                }
+            }
+        }
+        names.sort();
+        names.dedup();
+        names
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SUPPORTS: [ColorSupport; 3] = [
+        ColorSupport::TrueColor,
+        ColorSupport::Palette256,
+        ColorSupport::Palette16,
+    ];
+
+    #[test]
+    fn hashed_colors_are_stable_and_vivid() {
+        assert_eq!(hashed_color("tuitch"), hashed_color("tuitch"));
+        assert_ne!(hashed_color("alice"), hashed_color("bob"));
+        let (_, saturation, lightness) = rgb_to_hsl(hashed_color("tuitch"));
+        assert!((saturation - 0.7).abs() < 0.01);
+        assert!((lightness - 0.55).abs() < 0.01);
+    }
+
+    #[test]
+    fn readable_colors_keep_their_hue() {
+        let black = (0, 0, 0);
+        // Already readable, left alone.
+        assert_eq!(
+            readable((255, 255, 0), black, 4.5, ColorSupport::TrueColor),
+            Color::Rgb(255, 255, 0)
+        );
+        let navy = (0, 0, 139);
+        let lighter = readable(navy, black, 4.5, ColorSupport::TrueColor).rgb();
+        assert!(contrast(lighter, black) >= 4.5);
+        assert!((rgb_to_hsl(lighter).0 - rgb_to_hsl(navy).0).abs() < 1.0);
+        let yellow = (255, 255, 0);
+        let darker = readable(yellow, (255, 255, 255), 4.5, ColorSupport::TrueColor).rgb();
+        assert!(luminance(darker) < luminance(yellow));
+        assert!(contrast(darker, (255, 255, 255)) >= 4.5);
+    }
+
+    #[test]
+    fn readable_after_downgrading() {
+        for support in SUPPORTS {
+            for (background, min_contrast) in
+                [((0, 0, 0), 4.5), ((0, 0, 0), 7.0), ((255, 255, 255), 4.5)]
+            {
+                for user in 0..500 {
+                    let color = hashed_color(&format!("user{}", user));
+                    let shown = readable(color, background, min_contrast, support);
+                    assert_eq!(shown, shown.downgrade(support));
+                    assert!(
+                        contrast(shown.rgb(), background) >= min_contrast,
+                        "{:?} from {:?} on {:?} with {:?}",
+                        shown,
+                        color,
+                        background,
+                        support
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn downgrades_to_the_nearest_color() {
+        let red = Color::Rgb(255, 0, 0);
+        assert_eq!(red.downgrade(ColorSupport::TrueColor), red);
+        assert_eq!(red.downgrade(ColorSupport::Palette256), Color::Fixed(196));
+        assert_eq!(red.downgrade(ColorSupport::Palette16), Color::Ansi(9));
+        // Grays are closer to the gray ramp than to the cube.
+        let gray = Color::Rgb(128, 128, 128);
+        assert_eq!(gray.downgrade(ColorSupport::Palette256), Color::Fixed(244));
+        assert_eq!(gray.downgrade(ColorSupport::Palette16), Color::Ansi(8));
+        assert_eq!(Color::Fixed(208).downgrade(ColorSupport::Palette256), Color::Fixed(208));
+        assert_eq!(Color::Fixed(196).downgrade(ColorSupport::Palette16), Color::Ansi(9));
+        // Palette colours are already as few as it gets.
+        for support in SUPPORTS {
+            assert_eq!(Color::Ansi(3).downgrade(support), Color::Ansi(3));
+        }
+    }
+}
@@ -1,41 +1,668 @@
-use serde::{Deserialize, Serialize};
-use std::fs;
+use crate::badges::BadgeGlyph;
+use crate::credentials::{self, secret_path, Secret};
+use crate::input::DEFAULT_INPUT_HISTORY_SIZE;
+use crate::keys::KeyBindings;
+use crate::scrollback::DEFAULT_HISTORY_SIZE;
+use crate::theme::{Theme, BUILT_IN_THEMES};
+use chrono::format::{Item, StrftimeItems};
+use regex::Regex;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+    collections::BTreeMap,
+    env, fmt, fs, io,
+    path::{Path, PathBuf},
+};
+use toml_edit::DocumentMut;
 use twitch_irc::{login::StaticLoginCredentials, ClientConfig};
 
+/// The config schema this version of tuitch writes. Files from older
+/// versions are migrated when they're read, see `migrate`.
+pub const CONFIG_VERSION: u32 = 2;
+
+/// Timestamps in front of chat messages when the config doesn't say.
+pub const DEFAULT_TIMESTAMP_FORMAT: &str = "%H:%M";
+
 #[derive(Serialize, Deserialize)]
 pub struct UserConfig {
+    /// The schema the file was written with, files from before it was
+    /// versioned are version 1.
+    #[serde(default = "legacy_version")]
+    pub version: u32,
     pub username: String,
-    pub oauth_token: String,
-}
-
-pub async fn set_client_config(path: &str) -> ClientConfig<StaticLoginCredentials> {
-    // TODO: Change this, it's really bad.
-    if fs::metadata(path).is_ok() {
-        let config_file_content = fs::read_to_string(path).unwrap();
-        let config: UserConfig = toml::from_str(&config_file_content.as_str()).unwrap();
-        ClientConfig::new_simple(StaticLoginCredentials::new(
-            config.username,
-            Some(config.oauth_token),
-        ))
-    } else {
-        let config = UserConfig {
+    /// Tokens used to be saved here, they are moved to the secret file
+    /// next to the config on start and never written back.
+    #[serde(default, skip_serializing)]
+    pub oauth_token: Option<String>,
+    /// Channels joined on every start, along with any given with --channel.
+    #[serde(default)]
+    pub autojoin: Vec<String>,
+    /// strftime style format of the time in front of every message.
+    #[serde(default)]
+    pub timestamp_format: TimestampFormat,
+    /// Number of chat messages kept in each channel's scrollback.
+    #[serde(default = "default_history_size")]
+    pub history_size: usize,
+    /// Number of sent messages and commands kept for Up/Down and Ctrl-r.
+    #[serde(default = "default_input_history_size")]
+    pub input_history_size: usize,
+    /// File the sent messages and commands are saved to between sessions,
+    /// they are only kept for the session when this isn't set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input_history_file: Option<String>,
+    /// The theme used on start, a built-in one, one from `[themes.<name>]`
+    /// or a `themes/<name>.toml` file next to the config.
+    #[serde(default = "default_theme")]
+    pub theme: String,
+    /// Leave out the notices for joining and leaving a chat.
+    #[serde(default)]
+    pub hide_joins_parts: bool,
+    /// In channels we moderate, deleted messages stay readable, struck
+    /// through, instead of being replaced.
+    #[serde(default)]
+    pub show_deleted: bool,
+    #[serde(default)]
+    pub keys: KeyBindings,
+    #[serde(default)]
+    pub notifications: Notifications,
+    #[serde(default)]
+    pub emotes: EmoteConfig,
+    #[serde(default)]
+    pub badges: BadgeConfig,
+    #[serde(default)]
+    pub ignore: IgnoreConfig,
+    /// Themes of the user's own, in `[themes.<name>]` tables.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub themes: BTreeMap<String, Theme>,
+    /// Settings for a single channel, in `[channels.<name>]` tables,
+    /// that take the place of the ones above in that channel's tab.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub channels: BTreeMap<String, ChannelConfig>,
+    /// What migrating the file changed that the user should know about,
+    /// shown once the screen is up.
+    #[serde(skip)]
+    pub notices: Vec<String>,
+}
+
+/// When a message should get the user's attention, and how.
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct Notifications {
+    /// Messages containing any of these words are highlighted.
+    pub highlights: Vec<String>,
+    /// Highlight messages that mention our own name.
+    pub own_name: bool,
+    /// Highlight every message from these chatters.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub users: Vec<String>,
+    /// Highlight messages matching any of these regular expressions.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub patterns: Vec<Pattern>,
+    /// Ring the terminal bell for highlighted messages.
+    pub bell: bool,
+    /// Send a desktop notification for highlighted messages.
+    pub desktop: bool,
+    /// A command run for highlighted messages, `{channel}`, `{user}` and
+    /// `{message}` in its arguments are filled in.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub command: Vec<String>,
+}
+
+impl Default for Notifications {
+    fn default() -> Notifications {
+        Notifications {
+            highlights: Vec::new(),
+            own_name: true,
+            users: Vec::new(),
+            patterns: Vec::new(),
+            bell: false,
+            desktop: false,
+            command: Vec::new(),
+        }
+    }
+}
+
+/// How emotes in chat are drawn.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EmoteConfig {
+    /// Draw emotes as pictures in terminals with the kitty graphics
+    /// protocol, from the pictures in the cache directory.
+    pub images: bool,
+}
+
+/// The badges shown in front of chatters' names.
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct BadgeConfig {
+    pub show: bool,
+    /// How badges look, by name or `name/version` for a single version,
+    /// in place of the built-in ones.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub glyphs: BTreeMap<String, BadgeGlyph>,
+}
+
+impl Default for BadgeConfig {
+    fn default() -> BadgeConfig {
+        BadgeConfig {
+            show: true,
+            glyphs: BTreeMap::new(),
+        }
+    }
+}
+
+/// Chatters and messages kept out of chat.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IgnoreConfig {
+    /// Logins of the chatters whose messages are hidden, changed with
+    /// `:ignore` and `:unignore`.
+    pub users: Vec<String>,
+    /// Messages matching any of these regular expressions are hidden.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub patterns: Vec<Pattern>,
+    /// Show how many messages were hidden in their place, rather than
+    /// leaving them out without a trace.
+    pub show_hidden: bool,
+}
+
+/// The settings a `[channels.<name>]` table can change for one channel,
+/// anything left out is taken from the rest of the config.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChannelConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timestamp_format: Option<TimestampFormat>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hide_joins_parts: Option<bool>,
+    /// Replaces the highlight words from `[notifications]` in this channel.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub highlights: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub badges: Option<bool>,
+}
+
+/// A strftime style format, checked when the config is read since
+/// formatting a time with a broken one panics.
+#[derive(Clone, Serialize)]
+#[serde(transparent)]
+pub struct TimestampFormat(pub String);
+
+impl Default for TimestampFormat {
+    fn default() -> TimestampFormat {
+        TimestampFormat(DEFAULT_TIMESTAMP_FORMAT.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for TimestampFormat {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<TimestampFormat, D::Error> {
+        let format = String::deserialize(deserializer)?;
+        if StrftimeItems::new(&format).any(|item| item == Item::Error) {
+            return Err(de::Error::custom(format!(
+                "invalid timestamp format `{}`",
+                format
+            )));
+        }
+        Ok(TimestampFormat(format))
+    }
+}
+
+/// A regular expression, checked when the config is read.
+#[derive(Clone)]
+pub struct Pattern(pub Regex);
+
+impl Serialize for Pattern {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.0.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Pattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Pattern, D::Error> {
+        let pattern = String::deserialize(deserializer)?;
+        Regex::new(&pattern)
+            .map(Pattern)
+            .map_err(|error| de::Error::custom(format!("invalid pattern `{}`: {}", pattern, error)))
+    }
+}
+
+impl Default for UserConfig {
+    fn default() -> UserConfig {
+        UserConfig {
+            version: CONFIG_VERSION,
             username: String::new(),
-            oauth_token: String::new(),
+            oauth_token: None,
+            autojoin: Vec::new(),
+            timestamp_format: TimestampFormat::default(),
+            history_size: DEFAULT_HISTORY_SIZE,
+            input_history_size: DEFAULT_INPUT_HISTORY_SIZE,
+            input_history_file: None,
+            theme: default_theme(),
+            hide_joins_parts: false,
+            show_deleted: false,
+            keys: KeyBindings::default(),
+            notifications: Notifications::default(),
+            emotes: EmoteConfig::default(),
+            badges: BadgeConfig::default(),
+            ignore: IgnoreConfig::default(),
+            themes: BTreeMap::new(),
+            channels: BTreeMap::new(),
+            notices: Vec::new(),
+        }
+    }
+}
+
+impl UserConfig {
+    /// The `[channels.<name>]` table for `channel`, the name is matched
+    /// like Twitch does, without the '#' and whatever its case.
+    pub fn channel(&self, channel: &str) -> Option<&ChannelConfig> {
+        let channel = channel.trim_start_matches('#');
+        self.channels
+            .iter()
+            .find(|(name, _)| name.trim_start_matches('#').eq_ignore_ascii_case(channel))
+            .map(|(_, config)| config)
+    }
+
+    pub fn timestamp_format(&self, channel: &str) -> &str {
+        self.channel(channel)
+            .and_then(|config| config.timestamp_format.as_ref())
+            .unwrap_or(&self.timestamp_format)
+            .0
+            .as_str()
+    }
+
+    pub fn hide_joins_parts(&self, channel: &str) -> bool {
+        self.channel(channel)
+            .and_then(|config| config.hide_joins_parts)
+            .unwrap_or(self.hide_joins_parts)
+    }
+
+    pub fn highlights(&self, channel: &str) -> &[String] {
+        self.channel(channel)
+            .and_then(|config| config.highlights.as_deref())
+            .unwrap_or(&self.notifications.highlights)
+    }
+
+    pub fn show_badges(&self, channel: &str) -> bool {
+        self.channel(channel)
+            .and_then(|config| config.badges)
+            .unwrap_or(self.badges.show)
+    }
+}
+
+fn legacy_version() -> u32 {
+    1
+}
+
+fn default_history_size() -> usize {
+    DEFAULT_HISTORY_SIZE
+}
+
+fn default_input_history_size() -> usize {
+    DEFAULT_INPUT_HISTORY_SIZE
+}
+
+fn default_theme() -> String {
+    BUILT_IN_THEMES[0].to_string()
+}
+
+/// Environment variable naming the config file, `--config` wins over it.
+pub const CONFIG_ENV: &str = "TUITCH_CONFIG";
+
+/// The config file from before the XDG location, read from wherever tuitch was started.
+const LEGACY_CONFIG: &str = "Config.toml";
+
+/// Where the config file is: the `--config` path, then `$TUITCH_CONFIG`,
+/// then `$XDG_CONFIG_HOME/tuitch/config.toml`, with `XDG_CONFIG_HOME`
+/// defaulting to `~/.config`.
+pub fn config_path(cli: Option<PathBuf>) -> PathBuf {
+    if let Some(path) = cli {
+        return path;
+    }
+    if let Some(path) = env::var_os(CONFIG_ENV).filter(|path| !path.is_empty()) {
+        return PathBuf::from(path);
+    }
+    // Relative paths in XDG_CONFIG_HOME are invalid and ignored.
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .or_else(|| {
+            env::var_os("HOME")
+                .filter(|home| !home.is_empty())
+                .map(|home| PathBuf::from(home).join(".config"))
+        });
+    match config_home {
+        Some(config_home) => config_home.join("tuitch").join("config.toml"),
+        None => PathBuf::from(LEGACY_CONFIG),
+    }
+}
+
+/// Copy a config left in the current directory by an older version to
+/// `path`, unless there's a config there already.
+pub fn import_legacy_config(path: &Path) -> io::Result<()> {
+    let legacy = Path::new(LEGACY_CONFIG);
+    if path.exists() || !legacy.is_file() || path == legacy {
+        return Ok(());
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::copy(legacy, path).map(|_| ())
+}
+
+/// Why the config, or the credentials next to it, couldn't be read or written.
+#[derive(Debug)]
+pub enum ConfigError {
+    /// There's no config file yet, on the first start.
+    Missing(PathBuf),
+    Read {
+        path: PathBuf,
+        source: io::Error,
+    },
+    /// The file isn't valid TOML or a setting has the wrong type, with the
+    /// line and column (counting from 1) of the problem when it's known.
+    Parse {
+        path: PathBuf,
+        position: Option<(usize, usize)>,
+        message: String,
+    },
+    Write {
+        path: PathBuf,
+        source: io::Error,
+    },
+    /// The file was written by a newer tuitch, with a schema this one doesn't know.
+    NewerVersion {
+        path: PathBuf,
+        version: u32,
+    },
+}
+
+impl ConfigError {
+    pub(crate) fn parse(path: &Path, error: toml::de::Error) -> ConfigError {
+        let position = error.line_col().map(|(line, column)| (line + 1, column + 1));
+        let message = error.to_string();
+        // The position is shown in front instead, like a compiler would.
+        let message = match message.rsplit_once(" at line ") {
+            Some((message, _)) if position.is_some() => message.to_string(),
+            _ => message,
         };
-        create_config_file(path, config).await.unwrap();
-        ClientConfig::default()
+        ConfigError::Parse {
+            path: path.to_path_buf(),
+            position,
+            message,
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Missing(path) => write!(f, "There is no config file at {}", path.display()),
+            ConfigError::Read { path, source } => {
+                write!(f, "Couldn't read {}: {}", path.display(), source)
+            }
+            ConfigError::Parse {
+                path,
+                position: Some((line, column)),
+                message,
+            } => write!(f, "{}:{}:{}: {}", path.display(), line, column, message),
+            ConfigError::Parse {
+                path,
+                position: None,
+                message,
+            } => write!(f, "{}: {}", path.display(), message),
+            ConfigError::Write { path, source } => {
+                write!(f, "Couldn't write {}: {}", path.display(), source)
+            }
+            ConfigError::NewerVersion { path, version } => write!(
+                f,
+                "{} is version {} of the config, this tuitch only reads up to version {}",
+                path.display(),
+                version,
+                CONFIG_VERSION
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Read { source, .. } | ConfigError::Write { source, .. } => Some(source),
+            ConfigError::Missing(_)
+            | ConfigError::Parse { .. }
+            | ConfigError::NewerVersion { .. } => None,
+        }
     }
 }
 
-pub async fn create_config_file(path: &str, config: UserConfig) -> std::io::Result<()> {
-    let config_toml = toml::to_string(&config).unwrap();
-    fs::write(&path, config_toml)?;
+/// The client's login for `config`, read-only when `anonymous` is set or
+/// no token is saved.
+pub async fn set_client_config(
+    path: &Path,
+    config: &UserConfig,
+    anonymous: bool,
+) -> Result<ClientConfig<StaticLoginCredentials>, ConfigError> {
+    let secret_path = secret_path(path);
+    if anonymous || config.username.is_empty() {
+        return Ok(ClientConfig::default());
+    }
+    let token = credentials::unlock(&secret_path, &config.username).map_err(|source| {
+        ConfigError::Read {
+            path: secret_path,
+            source,
+        }
+    })?;
+    Ok(match token {
+        Some(token) => ClientConfig::new_simple(StaticLoginCredentials::new(
+            config.username.clone(),
+            Some(token),
+        )),
+        None => ClientConfig::default(),
+    })
+}
+
+pub async fn create_config_file(path: &Path, config: &UserConfig) -> Result<(), ConfigError> {
+    let write_error = |source| ConfigError::Write {
+        path: path.to_path_buf(),
+        source,
+    };
+    let config_toml = toml::to_string(config)
+        .map_err(|error| write_error(io::Error::new(io::ErrorKind::InvalidData, error)))?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(write_error)?;
+    }
+    fs::write(path, config_toml).map_err(write_error)
+}
+
+/// Change some settings in the config file and leave everything else,
+/// comments and layout included, as the user wrote it. A missing file is
+/// started from the default config. `edit` returns why the file couldn't
+/// be changed, if it couldn't.
+pub async fn edit_config_file(
+    path: &Path,
+    edit: impl FnOnce(&mut DocumentMut) -> Result<(), String>,
+) -> Result<(), ConfigError> {
+    if !path.exists() {
+        create_config_file(path, &UserConfig::default()).await?;
+    }
+    let content = fs::read_to_string(path).map_err(|source| ConfigError::Read {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let parse_error = |message: String| ConfigError::Parse {
+        path: path.to_path_buf(),
+        position: None,
+        message,
+    };
+    let mut document: DocumentMut = content
+        .parse()
+        .map_err(|error: toml_edit::TomlError| parse_error(error.message().to_string()))?;
+    edit(&mut document).map_err(parse_error)?;
+    fs::write(path, document.to_string()).map_err(|source| ConfigError::Write {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+pub async fn get_client_config(path: &Path) -> Result<UserConfig, ConfigError> {
+    let config_file_content = fs::read_to_string(path).map_err(|source| match source.kind() {
+        io::ErrorKind::NotFound => ConfigError::Missing(path.to_path_buf()),
+        _ => ConfigError::Read {
+            path: path.to_path_buf(),
+            source,
+        },
+    })?;
+    let mut config: UserConfig =
+        toml::from_str(&config_file_content).map_err(|error| ConfigError::parse(path, error))?;
+    if config.version > CONFIG_VERSION {
+        return Err(ConfigError::NewerVersion {
+            path: path.to_path_buf(),
+            version: config.version,
+        });
+    }
+    if config.version < CONFIG_VERSION {
+        migrate(path, &mut config)?;
+        create_config_file(path, &config).await?;
+    }
+    Ok(config)
+}
+
+/// Bring a config from an older version up to `CONFIG_VERSION`, one
+/// version at a time. Settings added since are filled in by their defaults.
+fn migrate(path: &Path, config: &mut UserConfig) -> Result<(), ConfigError> {
+    if config.version == 1 {
+        // The OAuth token moved out of the config file to the secret file.
+        // One that's already there is kept, the user is told if theirs differs.
+        if let Some(token) = config.oauth_token.take().filter(|token| !token.is_empty()) {
+            let token = credentials::oauth_token(&token);
+            let secret_path = secret_path(path);
+            match credentials::load(&secret_path) {
+                Ok(None) => credentials::save(&secret_path, &token, None).map_err(|source| {
+                    ConfigError::Write {
+                        path: secret_path,
+                        source,
+                    }
+                })?,
+                Ok(Some(Secret::Plain(saved))) if saved == token => {}
+                _ => config.notices.push(format!(
+                    "The OAuth token in {} was removed, the one in {} is used instead. \
+                     Enter :credentials to save another.",
+                    path.display(),
+                    secret_path.display()
+                )),
+            }
+        }
+        config.version = 2;
+    }
     Ok(())
 }
 
-pub async fn get_client_config(path: &str) -> UserConfig {
-    let config_file_content = fs::read_to_string(path).unwrap();
-    let config: UserConfig = toml::from_str(&config_file_content.as_str()).unwrap();
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    return config;
+    /// A directory of its own for each test, the secret file goes next to the config.
+    fn config_file(test: &str, content: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("tuitch-config-{}-{}", test, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.toml");
+        fs::write(&path, content).unwrap();
+        path
+    }
+
+    fn remove(path: &Path) {
+        fs::remove_dir_all(path.parent().unwrap()).ok();
+    }
+
+    #[tokio::test]
+    async fn reports_where_the_config_is_malformed() {
+        let path = config_file("malformed", "username = \"me\"\nhistory_size = \"x\"\n");
+        let error = get_client_config(&path).await.err().unwrap();
+        remove(&path);
+        assert!(matches!(error, ConfigError::Parse { position: Some((2, 16)), .. }));
+        let message = error.to_string();
+        assert!(
+            message.starts_with(&format!("{}:2:16: invalid type", path.display())),
+            "{}",
+            message
+        );
+    }
+
+    #[tokio::test]
+    async fn migrates_the_token_out_of_a_version_1_config() {
+        let path = config_file("migrate", "username = \"me\"\noauth_token = \"oauth:abc\"\n");
+        let config = get_client_config(&path).await.unwrap();
+        let saved = fs::read_to_string(&path).unwrap();
+        let secret = credentials::load(&secret_path(&path)).unwrap();
+        remove(&path);
+        assert_eq!(config.version, CONFIG_VERSION);
+        assert!(config.oauth_token.is_none());
+        assert!(config.notices.is_empty());
+        assert!(saved.contains("version = 2"), "{}", saved);
+        assert!(!saved.contains("oauth_token"), "{}", saved);
+        assert!(matches!(secret, Some(Secret::Plain(token)) if token == "abc"));
+    }
+
+    #[tokio::test]
+    async fn migrating_keeps_a_saved_token() {
+        let path = config_file("migrate-saved", "username = \"me\"\noauth_token = \"abc\"\n");
+        credentials::save(&secret_path(&path), "xyz", None).unwrap();
+        let config = get_client_config(&path).await.unwrap();
+        let secret = credentials::load(&secret_path(&path)).unwrap();
+        remove(&path);
+        assert_eq!(config.notices.len(), 1);
+        assert!(matches!(secret, Some(Secret::Plain(token)) if token == "xyz"));
+    }
+
+    #[test]
+    fn rejects_a_bad_timestamp_format() {
+        let config = |format: &str| {
+            toml::from_str::<UserConfig>(&format!(
+                "username = \"\"\ntimestamp_format = \"{}\"\n",
+                format
+            ))
+        };
+        assert_eq!(config("%H:%M:%S").unwrap().timestamp_format.0, "%H:%M:%S");
+        let error = config("%H:%Q").err().unwrap().to_string();
+        assert!(error.contains("invalid timestamp format `%H:%Q`"), "{}", error);
+    }
+
+    #[test]
+    fn channel_tables_override_the_rest() {
+        let config: UserConfig = toml::from_str(
+            r##"
+username = "me"
+timestamp_format = "%H:%M"
+hide_joins_parts = true
+
+[notifications]
+highlights = ["tuitch"]
+
+[badges]
+show = true
+
+[channels."#XQC"]
+timestamp_format = "%H:%M:%S"
+highlights = []
+badges = false
+
+[channels.forsen]
+hide_joins_parts = false
+"##,
+        )
+        .unwrap();
+        assert_eq!(config.timestamp_format("xqc"), "%H:%M:%S");
+        assert_eq!(config.timestamp_format("#xQc"), "%H:%M:%S");
+        assert!(config.highlights("xqc").is_empty());
+        assert!(!config.show_badges("xqc"));
+        assert!(config.hide_joins_parts("xqc"));
+
+        assert_eq!(config.timestamp_format("forsen"), "%H:%M");
+        assert_eq!(config.highlights("forsen"), ["tuitch"]);
+        assert!(config.show_badges("forsen"));
+        assert!(!config.hide_joins_parts("forsen"));
+
+        assert!(config.channel("other").is_none());
+        assert!(config.hide_joins_parts("other"));
+    }
 }
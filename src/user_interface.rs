@@ -1,44 +1,447 @@
-use owo_colors::OwoColorize;
-use std::io::{stdout, Write};
-use termion::terminal_size;
+use crate::channels::{Channel, Channels};
+use crate::input::InputBuffer;
+use crate::scrollback::Scrollback;
+use crate::theme::{Style, Theme};
+use std::io::{self, stdout, Stdout, Write};
+use termion::{
+    raw::{IntoRawMode, RawTerminal},
+    terminal_size,
+};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
-pub fn reset_screen() {
-    let (_x, y) = terminal_size().unwrap();
-    print!(
-        "{}{}> {}",
-        termion::clear::All,
-        termion::cursor::Goto(1, y),
-        placeholder(),
-    );
-    stdout().lock().flush().unwrap();
+const HOME_SCREEN: &[&str] = &[
+    "Commands (enter :help for the full list):",
+    "To join a channel's chat, enter :join <channel>, :part [channel] to leave it",
+    "Each joined chat gets a tab, switch with Alt-<n>, Ctrl-n/Ctrl-p, :tab <n>, :next or :prev",
+    "PageUp and PageDown scroll through a chat's history, End returns to the live chat",
+    "To log in, enter :login <username>, :logout to read chat anonymously",
+    "To save your login, enter :credentials <username>, your OAuth token is asked for",
+    "without showing it and saved to a file only you can read, encrypted if you give",
+    "a passphrase. (Please never share your OAuth token with anyone.)",
+    "'Ctrl-q' to exit the application",
+    "If you have any suggestions or would like to report any bugs, please visit the",
+    "project's GitHub repository at https://github.com/brandontdev/tuitch.",
+    "",
+];
+
+/// Columns the viewer list takes up next to the chat, and the fewest the
+/// chat keeps, the list isn't shown when there's no room for both.
+const VIEWER_PANE_WIDTH: usize = 24;
+const MIN_CHAT_WIDTH: usize = 40;
+
+const PLACEHOLDER: &str = "Enter a message or command";
+const PROMPT: &str = "> ";
+
+/// The state of the connection to Twitch, shown in the status bar.
+#[derive(Clone, Copy)]
+pub enum Connection {
+    /// No channel joined yet, the client only connects once one is.
+    Offline,
+    Connected,
+    Reconnecting,
+}
+
+/// Session details shown in the status bar.
+pub struct Status {
+    pub login: String,
+    pub connection: Connection,
+}
+
+impl Status {
+    pub fn new(login: String) -> Status {
+        Status {
+            login,
+            connection: Connection::Offline,
+        }
+    }
+}
+
+/// The terminal split into, from the top: the tab bar, the chat pane,
+/// the status bar and the input line.
+///
+/// Every frame is built from the shared state and only rows that changed
+/// since the last frame are written, so the screen is only ever drawn here.
+pub struct Screen {
+    stdout: RawTerminal<Stdout>,
+    width: u16,
+    height: u16,
+    rows: Vec<String>,
 }
 
-pub fn home_screen() {
+impl Screen {
+    /// Put the terminal into raw mode, reading stdin one key at a time.
+    pub fn new() -> io::Result<Screen> {
+        let (width, height) = terminal_size()?;
+        let mut screen = Screen {
+            stdout: stdout().into_raw_mode()?,
+            width,
+            height,
+            rows: Vec::new(),
+        };
+        write!(screen.stdout, "{}", termion::clear::All)?;
+        Ok(screen)
+    }
+
+    /// Pick up the new terminal size, the next frame is drawn from scratch.
+    pub fn resize(&mut self) -> io::Result<()> {
+        let (width, height) = terminal_size()?;
+        self.width = width;
+        self.height = height;
+        self.rows.clear();
+        write!(self.stdout, "{}", termion::clear::All)
+    }
+
+    /// Ring the terminal bell.
+    pub fn bell(&mut self) -> io::Result<()> {
+        write!(self.stdout, "\x07")?;
+        self.stdout.flush()
+    }
+
+    /// Hand pictures to the terminal, the chat draws them with placeholders.
+    pub fn send_images(&mut self, images: &[String]) -> io::Result<()> {
+        for image in images {
+            write!(self.stdout, "{}", image)?;
+        }
+        Ok(())
+    }
+
+    pub fn render(
+        &mut self,
+        channels: &Channels,
+        input: &InputBuffer,
+        status: &Status,
+        theme: &Theme,
+    ) -> io::Result<()> {
+        let width = self.width as usize;
+        let chat_height = (self.height as usize).saturating_sub(3).max(1);
+
+        let mut rows = Vec::with_capacity(self.height as usize);
+        rows.push(fit(&tab_bar(channels, theme), width));
+        let viewers = channels
+            .active()
+            .filter(|active| active.show_viewers && channels.is_showing_tab())
+            .filter(|_| width >= VIEWER_PANE_WIDTH + 1 + MIN_CHAT_WIDTH);
+        match viewers {
+            Some(active) => {
+                let chat_width = width - VIEWER_PANE_WIDTH - 1;
+                let chat = chat_pane(channels, chat_width, chat_height, theme);
+                let list = viewer_pane(active, VIEWER_PANE_WIDTH, chat_height, theme);
+                for (chat_row, list_row) in chat.into_iter().zip(list) {
+                    let padding = chat_width.saturating_sub(text_width(&chat_row));
+                    rows.push(format!("{}\x1b[0m{}│{}", chat_row, " ".repeat(padding), list_row));
+                }
+            }
+            None => rows.extend(chat_pane(channels, width, chat_height, theme)),
+        }
+        rows.push(fit(&status_bar(channels, status, width, theme), width));
+        let (input_line, cursor) = input_line(input, width, theme);
+        rows.push(input_line);
+
+        for (index, row) in rows.iter().enumerate() {
+            if self.rows.get(index) != Some(row) {
+                write!(
+                    self.stdout,
+                    "{}{}{}{}",
+                    termion::cursor::Goto(1, index as u16 + 1),
+                    row,
+                    termion::style::Reset,
+                    termion::clear::UntilNewline
+                )?;
+            }
+        }
+        self.rows = rows;
+
+        write!(
+            self.stdout,
+            "{}",
+            termion::cursor::Goto(cursor as u16 + 1, self.height)
+        )?;
+        self.stdout.flush()
+    }
+}
+
+/// Rows left for chat messages, between the tab bar and the status bar.
+pub fn chat_height() -> usize {
     let (_x, y) = terminal_size().unwrap();
-    print!(
-        "{}{}{}\r\n{}\r\n{}\r\n{}\r\n{}\r\n{}\r\n{}\r\n{}\r\n\n{}> {}",
-        termion::clear::All,
-        termion::cursor::Goto(1, 1),
-        "Commands:",
-        "To join a channel's chat, enter :join <channel>",
-        "To change your login credentials, enter :credentials <username> <OAuth token>",
-        "(Your OAuth token is saved locally, however at this time it is not encryptid,",
-        "please never share your OAuth token with anyone.)",
-        "'Ctrl-q' to exit the application",
-        "If you have any suggestions or would like to report any bugs, please visit the",
-        "project's GitHub repository at https://github.com/brandontdev/tuitch.",
-        termion::cursor::Goto(1, y),
-        placeholder(),
-    );
-    stdout().lock().flush().unwrap();
-}
-
-pub fn empty_line() {
-    print!("\r> {}", placeholder(),);
-    stdout().lock().flush().unwrap();
-}
-
-fn placeholder() -> String {
-    const PLACEHOLDER: &str = "Enter a message or command";
-    format!("{}\r{}", PLACEHOLDER.dimmed(), termion::cursor::Right(2))
+    (y as usize).saturating_sub(3).max(1)
+}
+
+fn tab_bar(channels: &Channels, theme: &Theme) -> String {
+    let mut tab_bar = String::new();
+    for (index, channel) in channels.tabs().iter().enumerate() {
+        let tab = format!(" {}:{} ", index + 1, channel.name);
+        if index == channels.active_index() && channels.is_showing_tab() {
+            tab_bar.push_str(&theme.active_tab.paint(tab));
+        } else {
+            tab_bar.push_str(&tab);
+        }
+    }
+    // The mentions view only gets a tab while it's on screen or has
+    // something new in it.
+    if channels.is_showing_mentions() {
+        tab_bar.push_str(&theme.active_tab.paint(" @mentions "));
+    } else if channels.unread_mentions() > 0 {
+        tab_bar.push_str(&theme.highlight.paint(format!(" @{} ", channels.unread_mentions())));
+    }
+    // Conversations follow, with how many whispers are unread in each.
+    let showing = channels.showing_whisper().map(|conversation| &conversation.partner);
+    for conversation in channels.conversations() {
+        if showing == Some(&conversation.partner) {
+            tab_bar.push_str(&theme.active_tab.paint(format!(" w:{} ", conversation.partner)));
+        } else if conversation.unread > 0 {
+            let tab = format!(" w:{} {} ", conversation.partner, conversation.unread);
+            tab_bar.push_str(&theme.highlight.paint(tab));
+        } else {
+            tab_bar.push_str(&format!(" w:{} ", conversation.partner));
+        }
+    }
+    tab_bar
+}
+
+/// Exactly `height` rows of chat, the newest at the bottom. Before any
+/// channel is joined this shows the home screen instead.
+fn chat_pane(channels: &Channels, width: usize, height: usize, theme: &Theme) -> Vec<String> {
+    if channels.is_showing_mentions() {
+        return scrollback_rows(&channels.mentions, width, height, theme);
+    }
+    if let Some(conversation) = channels.showing_whisper() {
+        return scrollback_rows(&conversation.messages, width, height, theme);
+    }
+    if let Some(active) = channels.active() {
+        return scrollback_rows(&active.messages, width, height, theme);
+    }
+    let mut rows = Vec::new();
+    for line in HOME_SCREEN {
+        rows.extend(wrap(line, width));
+    }
+    for notice in channels.notices.visible(height) {
+        rows.extend(wrap(&notice.text(), width));
+    }
+    let skip = rows.len().saturating_sub(height);
+    rows.drain(..skip);
+    rows.resize(height, String::new());
+    rows
+}
+
+/// The rows of `messages` that fit in `height`, with a notice along the
+/// bottom while scrolled up.
+fn scrollback_rows(messages: &Scrollback, width: usize, height: usize, theme: &Theme) -> Vec<String> {
+    let mut rows = Vec::new();
+    let scrolled = !messages.is_following();
+    let height = if scrolled { height - 1 } else { height };
+    let selected = messages.selected();
+    for line in messages.visible(height) {
+        if let Some(quote) = &line.quote {
+            rows.extend(wrap(quote, width));
+        }
+        let text = line.text();
+        // The message picked to reply to is marked in the margin.
+        if selected.is_some() && line.message_id.as_deref() == selected {
+            let text = text.strip_prefix(' ').unwrap_or(&text);
+            rows.extend(wrap(&format!("{}{}", theme.active_tab.paint("›"), text), width));
+            continue;
+        }
+        rows.extend(wrap(&text, width));
+    }
+    let skip = rows.len().saturating_sub(height);
+    rows.drain(..skip);
+    rows.resize(height, String::new());
+    if scrolled {
+        let notice = match messages.unseen() {
+            0 => "-- Scrolled up, End to return --".to_string(),
+            unseen => format!("-- {} new messages below, End to return --", unseen),
+        };
+        rows.push(fit(&theme.status_bar.paint(notice), width));
+    }
+    rows
+}
+
+/// Exactly `height` rows listing who's in `channel`, grouped by role under
+/// a count of them all.
+fn viewer_pane(channel: &Channel, width: usize, height: usize, theme: &Theme) -> Vec<String> {
+    let groups = channel.viewer_groups();
+    let shown: usize = groups.iter().map(|(_, names)| names.len()).sum();
+    let header = match &channel.viewer_filter {
+        Some(filter) => format!(" {} of {} users ({})", shown, channel.viewers.len(), filter),
+        None => format!(" {} users", channel.viewers.len()),
+    };
+    let padding = width.saturating_sub(text_width(&header));
+    let mut rows = vec![fit(
+        &theme.status_bar.paint(format!("{}{}", header, " ".repeat(padding))),
+        width,
+    )];
+    let title = Style {
+        bold: true,
+        ..Style::default()
+    };
+    let mut listed = 0;
+    for (role, names) in &groups {
+        rows.push(fit(&title.paint(format!("{} ({})", role.title(), names.len())), width));
+        for name in names {
+            // The last row says how many more there are once they don't fit.
+            if rows.len() + 1 >= height && listed + 1 < shown {
+                rows.truncate(height.saturating_sub(1).max(1));
+                let more = format!(" and {} more", shown - listed);
+                rows.push(fit(&theme.placeholder.paint(more), width));
+                rows.truncate(height);
+                rows.resize(height, String::new());
+                return rows;
+            }
+            rows.push(fit(&format!(" {}", name), width));
+            listed += 1;
+        }
+    }
+    rows.resize(height, String::new());
+    rows
+}
+
+fn status_bar(channels: &Channels, status: &Status, width: usize, theme: &Theme) -> String {
+    let channel = match (channels.active(), channels.showing_whisper()) {
+        _ if channels.is_showing_mentions() => "mentions".to_string(),
+        (_, Some(conversation)) => format!("whispers with {}", conversation.partner),
+        // The chat modes that are on follow the channel's name.
+        (Some(active), None) => match active.room_state.describe() {
+            modes if modes.is_empty() => format!("#{}", active.name),
+            modes => format!("#{} ({})", active.name, modes),
+        },
+        (None, None) => "home".to_string(),
+    };
+    let login = if status.login.is_empty() {
+        "anonymous"
+    } else {
+        status.login.as_str()
+    };
+    let connection = match status.connection {
+        Connection::Offline => "offline",
+        Connection::Connected => "connected",
+        Connection::Reconnecting => "reconnecting",
+    };
+    let bar = format!(" {} | {} | {}", channel, login, connection);
+    let padding = width.saturating_sub(text_width(&bar));
+    theme
+        .status_bar
+        .paint(format!("{}{}", bar, " ".repeat(padding)))
+}
+
+/// The prompt and as much of the input as fits, scrolled sideways to keep
+/// the cursor in view, and the column the cursor sits in.
+fn input_line(input: &InputBuffer, width: usize, theme: &Theme) -> (String, usize) {
+    if let Some((query, found)) = input.search_view() {
+        let prompt = match (query, found) {
+            ("", _) | (_, Some(_)) => "(reverse-i-search)`",
+            (_, None) => "(failed reverse-i-search)`",
+        };
+        let line = format!("{}{}': {}", prompt, query, found.unwrap_or_default());
+        let cursor = (prompt.len() + query.width()).min(width.saturating_sub(1));
+        return (fit(&line, width), cursor);
+    }
+    // Secrets only show as many stars as characters typed.
+    if let Some(prompt) = input.prompt() {
+        let label = prompt.label();
+        let (typed, cursor) = if prompt.is_secret() {
+            let typed = input.get_input().graphemes(true).count();
+            ("*".repeat(typed), input.before_cursor().graphemes(true).count())
+        } else {
+            (input.get_input().to_string(), input.before_cursor().width())
+        };
+        let line = format!("{}{}", theme.prompt.paint(&label), typed);
+        let cursor = (label.width() + cursor).min(width.saturating_sub(1));
+        return (fit(&line, width), cursor);
+    }
+    if input.is_empty() {
+        let line = format!(
+            "{}{}",
+            theme.prompt.paint(PROMPT),
+            theme.placeholder.paint(PLACEHOLDER)
+        );
+        return (fit(&line, width), PROMPT.len());
+    }
+    let room = width.saturating_sub(PROMPT.len()).max(1);
+    let graphemes: Vec<&str> = input.get_input().graphemes(true).collect();
+    let cursor = input.before_cursor().graphemes(true).count();
+    let columns = |graphemes: &[&str]| graphemes.iter().map(|g| g.width()).sum::<usize>();
+
+    let mut start = 0;
+    while start < cursor && columns(&graphemes[start..cursor]) >= room {
+        start += 1;
+    }
+    let mut line = theme.prompt.paint(PROMPT);
+    let mut used = 0;
+    for grapheme in &graphemes[start..] {
+        if used + grapheme.width() > room {
+            break;
+        }
+        line.push_str(grapheme);
+        used += grapheme.width();
+    }
+    (line, PROMPT.len() + columns(&graphemes[start..cursor]))
+}
+
+/// Cut `line` down to a single row of `width` columns.
+fn fit(line: &str, width: usize) -> String {
+    wrap(line, width).swap_remove(0)
+}
+
+/// Split `line` into rows no wider than `width` columns. Escape sequences
+/// take up no room, and styles still open at a break carry on in the next row.
+fn wrap(line: &str, width: usize) -> Vec<String> {
+    let mut rows = Vec::new();
+    let mut row = String::new();
+    let mut styles = String::new();
+    let mut column = 0;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            let mut sequence = String::from(c);
+            if chars.peek() == Some(&'[') {
+                for c in chars.by_ref() {
+                    sequence.push(c);
+                    if c != '[' && ('\x40'..='\x7e').contains(&c) {
+                        break;
+                    }
+                }
+            }
+            if sequence == "\x1b[0m" {
+                styles.clear();
+            } else if sequence.ends_with('m') {
+                styles.push_str(&sequence);
+            }
+            row.push_str(&sequence);
+            continue;
+        }
+        let c = if c == '\t' { ' ' } else { c };
+        if c.is_control() {
+            continue;
+        }
+        let char_width = c.width().unwrap_or(0);
+        if column + char_width > width && column > 0 {
+            row.push_str("\x1b[0m");
+            rows.push(std::mem::replace(&mut row, styles.clone()));
+            column = 0;
+        }
+        row.push(c);
+        column += char_width;
+    }
+    rows.push(row);
+    rows
+}
+
+/// Columns taken up by `text` once escape sequences are left out.
+fn text_width(text: &str) -> usize {
+    let mut width = 0;
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            for c in chars.by_ref() {
+                if c != '[' && ('\x40'..='\x7e').contains(&c) {
+                    break;
+                }
+            }
+        } else {
+            width += c.width().unwrap_or(0);
+        }
+    }
+    width
 }